@@ -0,0 +1,74 @@
+use aoc::intcode::loader;
+use aoc::intcode::{IntCodeMachine, RunState};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn load_program(day: &str) -> Vec<i64> {
+    let path = format!("{}/{}/input.txt", env!("CARGO_MANIFEST_DIR"), day);
+    loader::load_program(&path).unwrap()
+}
+
+// Day 9 part 2: the BOOST program in sensor boost mode.
+fn run_boost(program: &[i64], cached: bool) -> i64 {
+    let mut machine = IntCodeMachine::new(program);
+    machine.set_decode_cache(cached);
+    machine.provide_input(2);
+    machine.get_output().unwrap()
+}
+
+// Day 13 part 2: plays the arcade cabinet to the end by following the ball with the paddle.
+fn play_arcade(program: &[i64], cached: bool) -> i64 {
+    let mut hacked_registers = program.to_vec();
+    hacked_registers[0] = 2;
+
+    let mut machine = IntCodeMachine::new(&hacked_registers);
+    machine.set_decode_cache(cached);
+
+    let mut score = 0;
+    let mut ball_x: i64 = 0;
+    let mut paddle_x = 0;
+    let mut pending = vec![];
+    loop {
+        match machine.run().unwrap() {
+            RunState::Halted => return score,
+            RunState::AwaitingInput => machine.provide_input((ball_x - paddle_x).signum()),
+            RunState::Output(v) => pending.push(v),
+        }
+
+        if pending.len() == 3 {
+            match (pending[0], pending[2]) {
+                (-1, value) => score = value,
+                (x, 3) => paddle_x = x,
+                (x, 4) => ball_x = x,
+                _ => (),
+            }
+            pending.clear();
+        }
+    }
+}
+
+fn bench_interpreter(c: &mut Criterion) {
+    let boost = load_program("day9");
+    let arcade = load_program("day13");
+
+    let mut group = c.benchmark_group("boost");
+    for &cached in &[false, true] {
+        let name = if cached { "decoded" } else { "uncached" };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &cached, |b, &cached| {
+            b.iter(|| run_boost(&boost, cached))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("arcade");
+    group.sample_size(10);
+    for &cached in &[false, true] {
+        let name = if cached { "decoded" } else { "uncached" };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &cached, |b, &cached| {
+            b.iter(|| play_arcade(&arcade, cached))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_interpreter);
+criterion_main!(benches);
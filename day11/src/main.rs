@@ -1,5 +1,5 @@
-use aoc::intcode::IntCodeMachine;
-use aoc::utils::get_lines;
+use aoc::intcode::{IntCodeMachine, RunState};
+use aoc::intcode::loader::load_program;
 use std::cmp::min;
 use std::cmp::max;
 use std::collections::HashMap;
@@ -48,9 +48,10 @@ fn paint_with_starting_tile(starting_tile: Color, registers: &Vec<i64>) {
 
         machine.provide_input(color_input);
 
-        let color_output: i64 = match machine.get_output() {
-            None => break,
-            Some(v) => v,
+        let color_output: i64 = match machine.run().unwrap() {
+            RunState::Output(v) => v,
+            RunState::Halted => break,
+            RunState::AwaitingInput => panic!("Robot is waiting for input instead of painting"),
         };
 
         let new_color: Color = match color_output {
@@ -61,10 +62,10 @@ fn paint_with_starting_tile(starting_tile: Color, registers: &Vec<i64>) {
 
         painted.insert(current_coordinate.clone(), new_color);
 
-        let should_turn_left: bool = match machine.get_output().unwrap() {
-            0 => true,
-            1 => false,
-            _ => panic!(),
+        let should_turn_left: bool = match machine.run().unwrap() {
+            RunState::Output(0) => true,
+            RunState::Output(1) => false,
+            state => panic!("Expected a turn direction, got {:?}", state),
         };
 
         if should_turn_left {
@@ -114,11 +115,7 @@ fn paint_with_starting_tile(starting_tile: Color, registers: &Vec<i64>) {
 }
 
 fn main() {
-    let program = get_lines("input.txt")[0].clone();
-    let registers: Vec<i64> = program
-        .split(',')
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect();
+    let registers = load_program("input.txt").unwrap();
 
     // Part 1
     {
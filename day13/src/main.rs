@@ -1,6 +1,6 @@
 use aoc::intcode::IntCodeMachine;
-use aoc::intcode::IntCodeError;
-use aoc::utils::get_lines;
+use aoc::intcode::RunState;
+use aoc::intcode::loader::load_program;
 use std::cmp::max;
 use std::cmp::min;
 use std::collections::HashMap;
@@ -50,11 +50,7 @@ impl BlockType {
 }
 
 fn main() {
-    let program = get_lines("input.txt")[0].clone();
-    let registers: Vec<i64> = program
-        .split(',')
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect();
+    let registers = load_program("input.txt").unwrap();
 
     // Part 1
     {
@@ -63,9 +59,10 @@ fn main() {
         let mut screen: HashMap<Position, BlockType> = HashMap::new();
 
         loop {
-            let x = match computer.get_output() {
-                None => break,
-                Some(v) => v,
+            let x = match computer.run().unwrap() {
+                RunState::Output(v) => v,
+                RunState::Halted => break,
+                RunState::AwaitingInput => panic!("Cabinet asked for input in part 1"),
             };
 
             let y = computer.get_output().unwrap();
@@ -86,10 +83,9 @@ fn main() {
 
     // Part 2
     {
-        let mut hacked_registers = registers.clone();
-        hacked_registers[0] = 2;
-
-        let mut computer = IntCodeMachine::new(&hacked_registers);
+        // Two quarters for free play
+        let mut computer = IntCodeMachine::new(&registers);
+        computer.poke(0, 2).unwrap();
         let mut score = 0;
 
         let mut ball_x = 0;
@@ -100,9 +96,9 @@ fn main() {
         'outer: loop {
             let mut x = 1;
             loop {
-                match computer.get_output_v2() {
-                    Err(IntCodeError::ProgramComplete) => break 'outer,
-                    Err(IntCodeError::NeedInput) => {
+                match computer.run().unwrap() {
+                    RunState::Halted => break 'outer,
+                    RunState::AwaitingInput => {
                         computer.provide_input(match paddle_x == ball_x {
                             true => 0,
                             false => match paddle_x < ball_x {
@@ -111,7 +107,7 @@ fn main() {
                             }
                         });
                     },
-                    Ok(v) => {
+                    RunState::Output(v) => {
                         x = v;
                         break;
                     }
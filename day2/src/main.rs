@@ -1,62 +1,23 @@
-use aoc::utils::get_lines;
-
-enum OpCode {
-    ADD = 1,
-    MULTIPLY = 2,
-    END = 99
-}
-
-fn from_i32(i: i32) -> OpCode {
-    match i {
-        1 => OpCode::ADD,
-        2 => OpCode::MULTIPLY,
-        99 => OpCode::END,
-        _ => panic!("wtf")
-    }
-}
-
-fn run_intcodes(external_registers: &Vec<i32>, a: i32, b: i32) -> i32 {
-    let mut registers: Vec<i32> = external_registers.clone();
-    registers[1] = a;
-    registers[2] = b;
-
-    let mut index = 0;
-    loop {
-        match from_i32(registers[index]) {
-            OpCode::END => break,
-            OpCode::ADD => {
-                let target = registers[index + 3] as usize;
-                registers[target] = registers[registers[index + 1] as usize] + registers[registers[index + 2] as usize];
-            },
-            OpCode::MULTIPLY => {
-                let target = registers[index + 3] as usize;
-                registers[target] = registers[registers[index + 1] as usize] * registers[registers[index + 2] as usize];
-            }
-        }
-
-        index += 4;
-    }
-
-    registers[0]
-}
+use aoc::intcode::IntCodeMachine;
+use aoc::intcode::loader::load_program;
+use aoc::intcode::search::{grid, Search};
 
 fn main() {
-    let program = get_lines("input.txt")[0].clone();
-    let registers: Vec<i32> = program.split(',').map(|x| x.parse().unwrap()).collect();
+    let registers = load_program("input.txt").unwrap();
 
     // Part 1
     {
-        println!("{}", run_intcodes(&registers, 12, 2));
+        let mut computer = IntCodeMachine::new(&registers);
+        computer.poke_range(1, &[12, 2]).unwrap();
+        computer.run().unwrap();
+        println!("{}", computer.peek(0));
     }
 
     // Part 2
     {
-        for verb in 1..100 {
-            for noun in 1..100 {
-                if run_intcodes(&registers, noun, verb) == 19690720 {
-                    println!("{}", 100 * noun + verb);
-                }
-            }
-        }
+        let search = Search::new(&registers);
+        let result = search.find_first(grid(&[1, 2], 0..100).unwrap(), |e| e.machine.peek(0) == 19690720);
+        let patches = &result.matches[0].candidate.patches;
+        println!("{}", 100 * patches.get(1).unwrap() + patches.get(2).unwrap());
     }
 }
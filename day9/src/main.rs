@@ -1,9 +1,8 @@
-use aoc::utils::get_lines;
+use aoc::intcode::loader::load_program;
 use aoc::intcode::IntCodeMachine;
 
 fn main() {
-    let program = get_lines("input.txt")[0].clone();
-    let registers: Vec<i64> = program.split(',').map(|x| x.parse::<i64>().unwrap()).collect();
+    let registers = load_program("input.txt").unwrap();
 
     // Part 1
     {
@@ -0,0 +1,390 @@
+use aoc::intcode::io::{self, LineReader};
+use aoc::intcode::loader::{load_program, parse_program, read_program};
+use aoc::intcode::memory::MemoryBackend;
+use aoc::intcode::patch::PatchSet;
+use aoc::intcode::protect::ProtectionMode;
+use aoc::intcode::trace::JsonLinesWriter;
+use aoc::intcode::{IntCodeError, IntCodeMachine, RunState};
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::process;
+
+const USAGE: &str = "usage: intcode [options] <program | ->
+
+Runs an Intcode program. Without --input or --input-file, inputs are read from stdin.
+
+options:
+  --input <v1,v2,...>    input values (may be repeated)
+  --input-file <path>    read input values from a file
+  --output <format>      numbers (default), ascii or json
+  --poke <addr>=<value>  patch memory before running, e.g. 1=12,2=2 (may be repeated)
+  --trace <path>         write a JSON-lines execution trace
+  --budget <n>           stop with an error after n instructions
+  --protect <mode>       make the program image read-only: enforce stops at the first
+                         write into it, report only lists the writes
+  --paged                use paged memory
+  -h, --help             show this message
+
+exit status:
+  0   the program halted
+  1   the program failed
+  2   the program is waiting for input that was not given
+  64  bad command line
+  66  the program or input could not be loaded
+  74  the trace file could not be written";
+
+const EXIT_HALTED: i32 = 0;
+const EXIT_FAILED: i32 = 1;
+const EXIT_STARVED: i32 = 2;
+const EXIT_USAGE: i32 = 64;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_IO_ERROR: i32 = 74;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Format {
+    Numbers,
+    Ascii,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    program: String,
+    input: Vec<i64>,
+    input_file: Option<String>,
+    format: Format,
+    pokes: PatchSet,
+    trace: Option<String>,
+    budget: Option<u64>,
+    protect: Option<ProtectionMode>,
+    paged: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut program = None;
+    let mut options = Options {
+        program: String::new(),
+        input: vec![],
+        input_file: None,
+        format: Format::Numbers,
+        pokes: PatchSet::new(),
+        trace: None,
+        budget: None,
+        protect: None,
+        paged: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--input" => {
+                let text = value(arg)?;
+                options
+                    .input
+                    .extend(parse_program(&text).map_err(|e| format!("--input: {}", e))?);
+            }
+            "--input-file" => options.input_file = Some(value(arg)?),
+            "--output" => {
+                options.format = match value(arg)?.as_str() {
+                    "numbers" => Format::Numbers,
+                    "ascii" => Format::Ascii,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown output format '{}'", other)),
+                }
+            }
+            "--poke" => {
+                let patches: PatchSet =
+                    value(arg)?.parse().map_err(|e| format!("--poke: {}", e))?;
+                options.pokes.extend(&patches);
+            }
+            "--trace" => options.trace = Some(value(arg)?),
+            "--budget" => {
+                let text = value(arg)?;
+                options.budget = Some(
+                    text.parse::<u64>()
+                        .map_err(|_| format!("--budget expects a number, got '{}'", text))?,
+                );
+            }
+            "--protect" => {
+                options.protect = match value(arg)?.as_str() {
+                    "enforce" => Some(ProtectionMode::Enforce),
+                    "report" => Some(ProtectionMode::ReportOnly),
+                    other => return Err(format!("unknown protection mode '{}'", other)),
+                }
+            }
+            "--paged" => options.paged = true,
+            other if other.starts_with("--") => return Err(format!("unknown option '{}'", other)),
+            other => match program {
+                None => program = Some(other.to_owned()),
+                Some(_) => return Err(format!("unexpected argument '{}'", other)),
+            },
+        }
+    }
+
+    options.program = program.ok_or_else(|| String::from("no program given"))?;
+    Ok(Some(options))
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// Prints the protection violations seen since the last call.
+fn report_violations(machine: &mut IntCodeMachine) {
+    if let Some(protection) = machine.protection_mut() {
+        let dropped = protection.dropped_violations();
+        for violation in protection.take_violations() {
+            eprintln!("intcode: warning: {}", violation);
+        }
+        if dropped > 0 {
+            eprintln!(
+                "intcode: warning: {} more violations were not kept",
+                dropped
+            );
+        }
+    }
+}
+
+fn run(options: Options) -> i32 {
+    let loaded = match options.program.as_str() {
+        "-" => read_program(std::io::stdin()),
+        path => load_program(path),
+    };
+    let program = match loaded {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("intcode: {}: {}", options.program, e);
+            return EXIT_NO_INPUT;
+        }
+    };
+
+    let backend = match options.paged {
+        true => MemoryBackend::Paged,
+        false => MemoryBackend::Dense,
+    };
+    let mut machine = IntCodeMachine::with_memory(&program, backend);
+    if let Err(e) = options.pokes.apply_to(&mut machine) {
+        eprintln!("intcode: --poke: {}", e);
+        return EXIT_USAGE;
+    }
+    machine.set_instruction_budget(options.budget);
+    machine.set_backtraces(true);
+    if let Some(mode) = options.protect {
+        machine.protect_program(mode);
+    }
+    for v in options.input.iter() {
+        machine.provide_input(*v);
+    }
+
+    if let Some(path) = options.input_file.as_ref() {
+        match File::open(path) {
+            Ok(file) => machine.set_input_source(LineReader::new(BufReader::new(file))),
+            Err(e) => {
+                eprintln!("intcode: {}: {}", path, e);
+                return EXIT_NO_INPUT;
+            }
+        }
+    } else if options.input.is_empty() && options.program != "-" {
+        machine.set_input_source(io::stdin());
+    }
+
+    if let Some(path) = options.trace.as_ref() {
+        match JsonLinesWriter::create(path) {
+            Ok(writer) => machine.set_tracer(writer),
+            Err(e) => {
+                eprintln!("intcode: {}: {}", path, e);
+                return EXIT_IO_ERROR;
+            }
+        }
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut outputs = vec![];
+    let result = loop {
+        let state = machine.run();
+        report_violations(&mut machine);
+        match state {
+            Ok(RunState::Output(v)) => {
+                let _ = match options.format {
+                    Format::Numbers => writeln!(out, "{}", v),
+                    Format::Ascii if (0..128).contains(&v) => write!(out, "{}", v as u8 as char),
+                    Format::Ascii => writeln!(out, "{}", v),
+                    Format::Json => {
+                        outputs.push(v);
+                        Ok(())
+                    }
+                };
+            }
+            Ok(state) => break Ok(state),
+            Err(e) => break Err(e),
+        }
+    };
+
+    if options.format == Format::Json {
+        let values: Vec<String> = outputs.iter().map(|v| v.to_string()).collect();
+        let (state, error) = match &result {
+            Ok(RunState::Halted) => ("halted", String::from("null")),
+            Ok(_) => ("awaiting_input", String::from("null")),
+            Err(e) => ("failed", json_string(&e.root().to_string())),
+        };
+        let _ = writeln!(
+            out,
+            "{{\"outputs\":[{}],\"state\":\"{}\",\"error\":{},\"instructions\":{}}}",
+            values.join(","),
+            state,
+            error,
+            machine.instruction_count()
+        );
+    }
+    let _ = out.flush();
+    drop(machine);
+
+    match result {
+        Ok(RunState::Halted) => EXIT_HALTED,
+        Ok(_) => {
+            eprintln!("intcode: program is waiting for more input");
+            EXIT_STARVED
+        }
+        Err(e) => match e.root() {
+            IntCodeError::Device { message, .. } => {
+                eprintln!("intcode: reading input: {}", message);
+                EXIT_NO_INPUT
+            }
+            _ => {
+                eprintln!("intcode: {}", e);
+                EXIT_FAILED
+            }
+        },
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match parse_args(&args) {
+        Ok(Some(options)) => run(options),
+        Ok(None) => {
+            println!("{}", USAGE);
+            EXIT_HALTED
+        }
+        Err(message) => {
+            eprintln!("intcode: {}\n\n{}", message, USAGE);
+            EXIT_USAGE
+        }
+    };
+
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_string, parse_args, run, Format, ProtectionMode};
+    use super::{EXIT_FAILED, EXIT_HALTED, EXIT_IO_ERROR, EXIT_NO_INPUT, EXIT_STARVED};
+    use std::path::PathBuf;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    // A program written to a temporary file, removed again when dropped.
+    struct ProgramFile(PathBuf);
+
+    impl ProgramFile {
+        fn new(name: &str, program: &str) -> ProgramFile {
+            let path = std::env::temp_dir().join(format!(
+                "aoc_intcode_cli_test_{}_{}.txt",
+                name,
+                std::process::id()
+            ));
+            std::fs::write(&path, program).unwrap();
+            ProgramFile(path)
+        }
+
+        fn run(&self, options: &str) -> i32 {
+            let line = format!("{} {}", options, self.0.display());
+            run(parse_args(&args(&line)).unwrap().unwrap())
+        }
+    }
+
+    impl Drop for ProgramFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args(
+            "--input 1,2 --poke 0=2 game.txt --input 3 --output ascii --budget 100 --protect report --paged",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.program, "game.txt");
+        assert_eq!(options.input, vec![1, 2, 3]);
+        assert_eq!(options.pokes.to_string(), "0=2");
+        assert_eq!(options.format, Format::Ascii);
+        assert_eq!(options.budget, Some(100));
+        assert_eq!(options.protect, Some(ProtectionMode::ReportOnly));
+        assert!(options.paged);
+
+        assert_eq!(parse_args(&args("--help")), Ok(None));
+        assert!(parse_args(&args("--poke 0:2 p.txt")).is_err());
+        assert!(parse_args(&args("--output xml p.txt")).is_err());
+        assert!(parse_args(&args("--budget")).is_err());
+        assert!(parse_args(&args("--protect all p.txt")).is_err());
+        assert!(parse_args(&args("a.txt b.txt")).is_err());
+        assert!(parse_args(&args("")).is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(ProgramFile::new("halt", "99").run("--input 0"), EXIT_HALTED);
+        // Takes a second input that is never given.
+        assert_eq!(
+            ProgramFile::new("starve", "3,0,3,0,99").run("--input 1"),
+            EXIT_STARVED
+        );
+        assert_eq!(
+            ProgramFile::new("fault", "98").run("--input 0"),
+            EXIT_FAILED
+        );
+        assert_eq!(
+            ProgramFile::new("budget", "1105,1,0").run("--input 0 --budget 10"),
+            EXIT_FAILED
+        );
+        assert_eq!(
+            ProgramFile::new("trace", "99").run("--input 0 --trace /nonexistent/trace.jsonl"),
+            EXIT_IO_ERROR
+        );
+        assert_eq!(
+            run(parse_args(&args("/nonexistent/intcode.txt"))
+                .unwrap()
+                .unwrap()),
+            EXIT_NO_INPUT
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+    }
+}
@@ -0,0 +1,173 @@
+// Talks to programs that read and write ASCII text. Output values outside the ASCII range,
+// such as a final answer printed after the text, are kept apart from the text lines.
+
+use super::{IntCodeError, IntCodeMachine, RunState};
+use std::io::{self, BufRead, Write};
+
+pub struct AsciiMachine {
+    machine: IntCodeMachine,
+    line: String,
+    lines: Vec<String>,
+    values: Vec<i64>,
+}
+
+impl AsciiMachine {
+    pub fn new(machine: IntCodeMachine) -> AsciiMachine {
+        AsciiMachine {
+            machine,
+            line: String::new(),
+            lines: vec![],
+            values: vec![],
+        }
+    }
+
+    pub fn machine(&self) -> &IntCodeMachine {
+        &self.machine
+    }
+
+    pub fn into_machine(self) -> IntCodeMachine {
+        self.machine
+    }
+
+    pub fn send(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.machine.provide_input(byte as i64);
+        }
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.machine.provide_input(b'\n' as i64);
+    }
+
+    // Runs until the program halts or wants more input than has been sent.
+    pub fn run(&mut self) -> Result<RunState, IntCodeError> {
+        loop {
+            match self.machine.run()? {
+                RunState::Output(v) if (0..128).contains(&v) => {
+                    if v == b'\n' as i64 {
+                        self.lines.push(std::mem::take(&mut self.line));
+                    } else {
+                        self.line.push(v as u8 as char);
+                    }
+                }
+                RunState::Output(v) => self.values.push(v),
+                state => return Ok(state),
+            }
+        }
+    }
+
+    pub fn take_lines(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lines)
+    }
+
+    // Text after the last newline, typically a prompt.
+    pub fn partial_line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn take_values(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.values)
+    }
+
+    // Shows the program's text on `output` and sends it each line read from `input` until
+    // it halts or the input ends.
+    pub fn interact<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut output: W,
+    ) -> io::Result<RunState> {
+        let mut input = input.lines();
+        loop {
+            let state = self.run().map_err(io::Error::other)?;
+            for line in self.take_lines() {
+                writeln!(output, "{}", line)?;
+            }
+            for value in self.take_values() {
+                writeln!(output, "[{}]", value)?;
+            }
+            write!(output, "{}", std::mem::take(&mut self.line))?;
+            output.flush()?;
+
+            if state == RunState::Halted {
+                return Ok(state);
+            }
+
+            match input.next() {
+                Some(line) => self.send_line(&line?),
+                None => return Ok(state),
+            }
+        }
+    }
+
+    pub fn play(&mut self) -> io::Result<RunState> {
+        self.interact(io::stdin().lock(), io::stdout().lock())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsciiMachine;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{IntCodeMachine, RunState};
+
+    // Prints "hi\n> ", echoes one line back upper-cased, then prints 1000 and halts.
+    fn shout() -> Vec<i64> {
+        assemble(
+            "
+                    out #104
+                    out #105
+                    out #10
+                    out #62
+                    out #32
+            loop:   in  c
+                    eq  c, #10, flag
+                    jt  flag, #done
+                    lt  c, #97, flag
+                    jt  flag, #emit
+                    add c, #-32, c
+            emit:   out c
+                    jt  #1, #loop
+            done:   out #10
+                    out #1000
+                    end
+            c:      .data 0
+            flag:   .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lines_and_values() {
+        let mut ascii = AsciiMachine::new(IntCodeMachine::new(&shout()));
+        assert_eq!(ascii.run(), Ok(RunState::AwaitingInput));
+        assert_eq!(ascii.take_lines(), vec!["hi"]);
+        assert_eq!(ascii.partial_line(), "> ");
+
+        ascii.send("go ");
+        assert_eq!(ascii.run(), Ok(RunState::AwaitingInput));
+        ascii.send_line("on!");
+        assert_eq!(ascii.run(), Ok(RunState::Halted));
+        assert_eq!(ascii.take_lines(), vec!["> GO ON!"]);
+        assert_eq!(ascii.take_values(), vec![1000]);
+        assert_eq!(ascii.partial_line(), "");
+    }
+
+    #[test]
+    fn test_interact() {
+        let mut ascii = AsciiMachine::new(IntCodeMachine::new(&shout()));
+        let mut output: Vec<u8> = vec![];
+        let state = ascii.interact("abc\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(state, RunState::Halted);
+        assert_eq!(String::from_utf8(output).unwrap(), "hi\n> ABC\n[1000]\n");
+
+        let mut ascii = AsciiMachine::new(IntCodeMachine::new(&shout()));
+        let state = ascii.interact("".as_bytes(), std::io::sink()).unwrap();
+        assert_eq!(state, RunState::AwaitingInput);
+
+        let mut ascii = AsciiMachine::new(IntCodeMachine::new(&[3, 0, 1105, 1, 0]));
+        ascii.send_line("x");
+        assert!(ascii.interact("".as_bytes(), std::io::sink()).is_err());
+    }
+}
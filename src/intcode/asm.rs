@@ -0,0 +1,577 @@
+// Assembler for a small textual Intcode language.
+//
+//     start:  in  rel[1]            ; labels end with a colon
+//             add rel[1], #10, 100  ; #imm, rel[offset] and plain position operands
+//             jt  100, #start       ; labels may be used wherever a number is expected
+//             end
+//     table:  .data 1, 2, start+3   ; raw words
+
+use super::{OpCode, ParameterMode};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleErrorKind {
+    UnexpectedCharacter(char),
+    InvalidNumber(String),
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    OperandCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    ImmediateWriteTarget,
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    Expected(&'static str),
+    // An expression whose value does not fit in an Intcode word.
+    Overflow,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AssembleErrorKind,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            AssembleErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            AssembleErrorKind::InvalidNumber(n) => write!(f, "invalid number '{}'", n),
+            AssembleErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic '{}'", m),
+            AssembleErrorKind::UnknownDirective(d) => write!(f, "unknown directive '.{}'", d),
+            AssembleErrorKind::OperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' takes {} operand(s) but {} were given",
+                mnemonic, expected, found
+            ),
+            AssembleErrorKind::ImmediateWriteTarget => {
+                write!(f, "write target cannot be an immediate-mode operand")
+            }
+            AssembleErrorKind::UndefinedLabel(l) => write!(f, "undefined label '{}'", l),
+            AssembleErrorKind::DuplicateLabel(l) => write!(f, "label '{}' is already defined", l),
+            AssembleErrorKind::Expected(what) => write!(f, "expected {}", what),
+            AssembleErrorKind::Overflow => write!(f, "value does not fit in 64 bits"),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Directive(String),
+    Hash,
+    Comma,
+    Colon,
+    LeftBracket,
+    RightBracket,
+    Plus,
+    Minus,
+}
+
+#[derive(Debug)]
+enum Atom {
+    Number(i64),
+    Label(String),
+}
+
+// A sum of signed numbers and labels, e.g. `table+3` or `-2`.
+#[derive(Debug)]
+struct Expr {
+    terms: Vec<(i64, Atom, usize)>,
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: ParameterMode,
+    value: Expr,
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction(OpCode, Vec<Operand>),
+    Data(Vec<Expr>),
+}
+
+struct Line {
+    label: Option<(String, usize)>,
+    item: Option<Item>,
+}
+
+struct Parser {
+    line: usize,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    end_column: usize,
+}
+
+impl Parser {
+    fn error(&self, column: usize, kind: AssembleErrorKind) -> AssembleError {
+        AssembleError {
+            line: self.line,
+            column,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(t, _)| t)
+    }
+
+    fn column(&self) -> usize {
+        match self.tokens.get(self.position) {
+            Some((_, c)) => *c,
+            None => self.end_column,
+        }
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn parse_atom(&mut self, sign: i64) -> Result<(i64, Atom, usize), AssembleError> {
+        let column = self.column();
+        match self.next() {
+            Some((Token::Number(n), _)) => match n.parse::<i64>() {
+                Ok(v) => Ok((sign, Atom::Number(v), column)),
+                Err(_) => Err(self.error(column, AssembleErrorKind::InvalidNumber(n))),
+            },
+            Some((Token::Ident(name), _)) => Ok((sign, Atom::Label(name), column)),
+            _ => Err(self.error(column, AssembleErrorKind::Expected("a number or label"))),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, AssembleError> {
+        let mut terms = vec![];
+        let sign = if self.peek() == Some(&Token::Minus) {
+            self.next();
+            -1
+        } else {
+            1
+        };
+        terms.push(self.parse_atom(sign)?);
+
+        loop {
+            let sign = match self.peek() {
+                Some(Token::Plus) => 1,
+                Some(Token::Minus) => -1,
+                _ => break,
+            };
+            self.next();
+            terms.push(self.parse_atom(sign)?);
+        }
+
+        Ok(Expr { terms })
+    }
+
+    fn parse_operand(&mut self) -> Result<(Operand, usize), AssembleError> {
+        let column = self.column();
+        let is_relative = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Ident(name)), Some((Token::LeftBracket, _))) => name == "rel",
+            _ => false,
+        };
+
+        let operand = if self.peek() == Some(&Token::Hash) {
+            self.next();
+            Operand {
+                mode: ParameterMode::Immediate,
+                value: self.parse_expr()?,
+            }
+        } else if is_relative {
+            self.next();
+            self.next();
+            let value = self.parse_expr()?;
+            match self.next() {
+                Some((Token::RightBracket, _)) => (),
+                _ => {
+                    return Err(self.error(self.column(), AssembleErrorKind::Expected("']'")));
+                }
+            }
+            Operand {
+                mode: ParameterMode::Relative,
+                value,
+            }
+        } else {
+            Operand {
+                mode: ParameterMode::Position,
+                value: self.parse_expr()?,
+            }
+        };
+
+        Ok((operand, column))
+    }
+
+    fn expect_separator(&mut self) -> Result<(), AssembleError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(Token::Comma) => {
+                self.next();
+                if self.at_end() {
+                    Err(self.error(self.column(), AssembleErrorKind::Expected("an operand")))
+                } else {
+                    Ok(())
+                }
+            }
+            Some(_) => Err(self.error(self.column(), AssembleErrorKind::Expected("','"))),
+        }
+    }
+}
+
+fn tokenize(line_number: usize, line: &str) -> Result<(Vec<(Token, usize)>, usize), AssembleError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c == ';' {
+            break;
+        }
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let single = match c {
+            '#' => Some(Token::Hash),
+            ',' => Some(Token::Comma),
+            ':' => Some(Token::Colon),
+            '[' => Some(Token::LeftBracket),
+            ']' => Some(Token::RightBracket),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            _ => None,
+        };
+
+        if let Some(token) = single {
+            tokens.push((token, column));
+            i += 1;
+            continue;
+        }
+
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let start = if c == '.' { i + 1 } else { i };
+        let mut end = start;
+        while end < chars.len() && is_word(chars[end]) {
+            end += 1;
+        }
+
+        if end == start {
+            return Err(AssembleError {
+                line: line_number,
+                column,
+                kind: AssembleErrorKind::UnexpectedCharacter(c),
+            });
+        }
+
+        let word: String = chars[start..end].iter().collect();
+        let token = if c == '.' {
+            Token::Directive(word)
+        } else if c.is_ascii_digit() {
+            Token::Number(word)
+        } else {
+            Token::Ident(word)
+        };
+
+        tokens.push((token, column));
+        i = end;
+    }
+
+    Ok((tokens, chars.len() + 1))
+}
+
+fn parse_line(line_number: usize, line: &str) -> Result<Line, AssembleError> {
+    let (tokens, end_column) = tokenize(line_number, line)?;
+    let mut parser = Parser {
+        line: line_number,
+        tokens,
+        position: 0,
+        end_column,
+    };
+
+    let mut label = None;
+    if let (Some((Token::Ident(name), column)), Some((Token::Colon, _))) =
+        (parser.tokens.first(), parser.tokens.get(1))
+    {
+        label = Some((name.clone(), *column));
+        parser.position = 2;
+    }
+
+    let column = parser.column();
+    let item = match parser.next() {
+        None => return Ok(Line { label, item: None }),
+        Some((Token::Directive(name), _)) => {
+            if name != "data" {
+                return Err(parser.error(column, AssembleErrorKind::UnknownDirective(name)));
+            }
+
+            let mut values = vec![];
+            while !parser.at_end() {
+                values.push(parser.parse_expr()?);
+                parser.expect_separator()?;
+            }
+            Item::Data(values)
+        }
+        Some((Token::Ident(mnemonic), _)) => {
+            let opcode = match OpCode::from_mnemonic(&mnemonic) {
+                Some(o) => o,
+                None => {
+                    return Err(parser.error(column, AssembleErrorKind::UnknownMnemonic(mnemonic)))
+                }
+            };
+
+            let mut operands = vec![];
+            while !parser.at_end() {
+                let (operand, operand_column) = parser.parse_operand()?;
+                let number = operands.len() as i64 + 1;
+                if opcode.write_parameter() == Some(number)
+                    && operand.mode == ParameterMode::Immediate
+                {
+                    return Err(
+                        parser.error(operand_column, AssembleErrorKind::ImmediateWriteTarget)
+                    );
+                }
+
+                operands.push(operand);
+                parser.expect_separator()?;
+            }
+
+            if operands.len() != opcode.parameter_count() {
+                return Err(parser.error(
+                    column,
+                    AssembleErrorKind::OperandCount {
+                        mnemonic,
+                        expected: opcode.parameter_count(),
+                        found: operands.len(),
+                    },
+                ));
+            }
+
+            Item::Instruction(opcode, operands)
+        }
+        Some(_) => {
+            return Err(parser.error(
+                column,
+                AssembleErrorKind::Expected("a mnemonic or directive"),
+            ))
+        }
+    };
+
+    Ok(Line {
+        label,
+        item: Some(item),
+    })
+}
+
+fn evaluate(line: usize, expr: &Expr, labels: &HashMap<String, i64>) -> Result<i64, AssembleError> {
+    let mut total: i64 = 0;
+    for (sign, atom, column) in expr.terms.iter() {
+        let value = match atom {
+            Atom::Number(n) => *n,
+            Atom::Label(name) => match labels.get(name) {
+                Some(v) => *v,
+                None => {
+                    return Err(AssembleError {
+                        line,
+                        column: *column,
+                        kind: AssembleErrorKind::UndefinedLabel(name.clone()),
+                    })
+                }
+            },
+        };
+        total = match sign.checked_mul(value).and_then(|v| total.checked_add(v)) {
+            Some(total) => total,
+            None => {
+                return Err(AssembleError {
+                    line,
+                    column: *column,
+                    kind: AssembleErrorKind::Overflow,
+                })
+            }
+        };
+    }
+
+    Ok(total)
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssembleError> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = vec![];
+    let mut address: i64 = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let Line { label, item } = parse_line(line_number, line)?;
+
+        if let Some((name, column)) = label {
+            if labels.insert(name.clone(), address).is_some() {
+                return Err(AssembleError {
+                    line: line_number,
+                    column,
+                    kind: AssembleErrorKind::DuplicateLabel(name),
+                });
+            }
+        }
+
+        if let Some(item) = item {
+            address += match &item {
+                Item::Instruction(_, operands) => operands.len() as i64 + 1,
+                Item::Data(values) => values.len() as i64,
+            };
+            items.push((line_number, item));
+        }
+    }
+
+    let mut program = vec![];
+    for (line, item) in items.iter() {
+        match item {
+            Item::Instruction(opcode, operands) => {
+                let mut instruction = opcode.code();
+                let mut scale = 100;
+                for operand in operands.iter() {
+                    instruction += operand.mode.code() * scale;
+                    scale *= 10;
+                }
+
+                program.push(instruction);
+                for operand in operands.iter() {
+                    program.push(evaluate(*line, &operand.value, &labels)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in values.iter() {
+                    program.push(evaluate(*line, value, &labels)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AssembleError, AssembleErrorKind};
+    use crate::intcode::IntCodeMachine;
+
+    #[test]
+    fn test_assemble_quine() {
+        let source = "
+            start:  rbo #1
+                    out rel[-1]
+                    add 100, #1, 100
+                    eq  100, #16, 101
+                    jf  101, #start
+                    end
+        ";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99]
+        );
+    }
+
+    #[test]
+    fn test_assemble_labels_and_data() {
+        let source = "
+                    in  value          ; read into the data cell
+                    mul value, #2, value
+                    out value
+                    jt  #1, #done
+            value:  .data 0
+            done:   end
+                    .data done+1, -3
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 12, 0, 99, 13, -3]
+        );
+
+        let mut machine = IntCodeMachine::new(&program);
+        machine.provide_input(21);
+        assert_eq!(machine.get_output(), Some(42));
+        assert_eq!(machine.get_output(), None);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(
+            assemble("add 1, 2, 3\n  add #1, #2, #3"),
+            Err(AssembleError {
+                line: 2,
+                column: 15,
+                kind: AssembleErrorKind::ImmediateWriteTarget,
+            })
+        );
+
+        assert_eq!(
+            assemble("  in #5").unwrap_err().kind,
+            AssembleErrorKind::ImmediateWriteTarget
+        );
+
+        assert_eq!(
+            assemble("jt 1, #nowhere").unwrap_err(),
+            AssembleError {
+                line: 1,
+                column: 8,
+                kind: AssembleErrorKind::UndefinedLabel(String::from("nowhere")),
+            }
+        );
+
+        assert_eq!(
+            assemble("out 1, 2").unwrap_err().kind,
+            AssembleErrorKind::OperandCount {
+                mnemonic: String::from("out"),
+                expected: 1,
+                found: 2,
+            }
+        );
+
+        assert_eq!(
+            assemble("a: end\na: end").unwrap_err(),
+            AssembleError {
+                line: 2,
+                column: 1,
+                kind: AssembleErrorKind::DuplicateLabel(String::from("a")),
+            }
+        );
+
+        assert_eq!(
+            assemble("jmp 4").unwrap_err().kind,
+            AssembleErrorKind::UnknownMnemonic(String::from("jmp"))
+        );
+
+        assert_eq!(
+            assemble(".data 9223372036854775807+1").unwrap_err(),
+            AssembleError {
+                line: 1,
+                column: 27,
+                kind: AssembleErrorKind::Overflow,
+            }
+        );
+    }
+}
@@ -0,0 +1,260 @@
+// Best-effort call stacks for programs that follow the calling convention described in
+// `decompile`. Inside a function with an `rbo #N` prologue the return address is at
+// rel[-N] and the caller's relative base is N below the current one, so each frame can be
+// unwound from the one below it. Unwinding stops at `main`, at code that is not part of a
+// known function, or after `MAX_FRAMES` frames. At most `MAX_SLOTS` cells of each frame are
+// captured.
+
+use super::decompile::{is_return, Decompiled};
+use super::disasm::Instruction;
+use std::fmt;
+
+pub const MAX_FRAMES: usize = 64;
+pub const MAX_SLOTS: usize = 16;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Frame {
+    // Where the frame is executing: the faulting instruction for the innermost frame and
+    // the return address for the others.
+    pub address: usize,
+    pub function: Option<String>,
+    pub relative_base: i64,
+    pub return_address: Option<i64>,
+    // The frame's arguments and locals, rel[-N+1] to rel[-1], up to `MAX_SLOTS` of them.
+    pub slots: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    pub truncated: bool,
+}
+
+// `decompiled` gives the functions to unwind through; `read` gives the current value of
+// any cell.
+pub(crate) fn capture<F: Fn(usize) -> i64>(
+    decompiled: &Decompiled,
+    read: F,
+    instruction: usize,
+    relative_base: i64,
+) -> Backtrace {
+    let mut backtrace = Backtrace::default();
+    let mut address = instruction;
+    let mut base = relative_base;
+
+    loop {
+        if backtrace.frames.len() == MAX_FRAMES {
+            backtrace.truncated = true;
+            return backtrace;
+        }
+
+        let function = decompiled.function_at(address);
+        let mut frame = Frame {
+            address,
+            function: function.map(|f| f.name.clone()),
+            relative_base: base,
+            return_address: None,
+            slots: vec![],
+        };
+
+        let frame_size = match function {
+            Some(f) if f.entry != 0 && f.frame_size > 0 => f.frame_size as i64,
+            _ => {
+                backtrace.frames.push(frame);
+                return backtrace;
+            }
+        };
+
+        // Before the prologue has run, or after the epilogue, the frame is already gone and
+        // the return address is at rel[0].
+        let cells: Vec<i64> = (address..address + 4).map(&read).collect();
+        let entered = address != function.map(|f| f.entry).unwrap_or(0)
+            && !Instruction::decode(&cells, 0)
+                .map(|i| is_return(&i))
+                .unwrap_or(false);
+        let slot = match entered {
+            true => base.checked_sub(frame_size),
+            false => Some(base),
+        };
+        let slot = match slot {
+            Some(slot) if slot >= 0 => slot,
+            _ => {
+                backtrace.frames.push(frame);
+                return backtrace;
+            }
+        };
+
+        let return_address = read(slot as usize);
+        frame.return_address = Some(return_address);
+        if entered {
+            frame.slots = (slot as usize + 1..base as usize)
+                .take(MAX_SLOTS)
+                .map(&read)
+                .collect();
+        }
+        backtrace.frames.push(frame);
+
+        if return_address < 0 {
+            return backtrace;
+        }
+        address = return_address as usize;
+        base = slot;
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} in {}, relative base {}",
+            self.address,
+            self.function.as_deref().unwrap_or("?"),
+            self.relative_base
+        )?;
+        if !self.slots.is_empty() {
+            let slots: Vec<String> = self.slots.iter().map(|v| v.to_string()).collect();
+            write!(f, ", frame [{}]", slots.join(", "))?;
+        }
+        if let Some(address) = self.return_address {
+            write!(f, ", returns to {}", address)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines: Vec<String> = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| format!("#{:<3} {}", i, frame))
+            .collect();
+        if self.truncated {
+            lines.push(String::from("..."));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_SLOTS;
+    use crate::intcode::debugger::Debugger;
+    use crate::intcode::{IntCodeError, IntCodeMachine};
+
+    // main calls f(3); f(n) calls f(n - 1) until n is 0, then reads a negative address.
+    const PROGRAM: [i64; 44] = [
+        109, 100, 21101, 3, 0, 1, 21101, 13, 0, 0, 1105, 1, 15, 99, 0, 109, 2, 1206, -1, 40, 21201,
+        -1, -1, 1, 21101, 31, 0, 0, 1105, 1, 15, 109, -2, 2106, 0, 0, 0, 0, 0, 0, 1, -5, 0, 0,
+    ];
+
+    #[test]
+    fn test_recursive_backtrace() {
+        let fault = IntCodeError::NegativeAddress {
+            address: 40,
+            target: -5,
+        };
+        let mut machine = IntCodeMachine::new(&PROGRAM);
+        assert_eq!(machine.run(), Err(fault.clone()));
+
+        let mut machine = IntCodeMachine::new(&PROGRAM);
+        machine.set_backtraces(true);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.root(), &fault);
+
+        let backtrace = error.backtrace().unwrap();
+        let frames: Vec<(usize, Option<i64>, Vec<i64>)> = backtrace
+            .frames
+            .iter()
+            .map(|f| (f.address, f.return_address, f.slots.clone()))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (40, Some(31), vec![0]),
+                (31, Some(31), vec![1]),
+                (31, Some(31), vec![2]),
+                (31, Some(13), vec![3]),
+                (13, None, vec![]),
+            ]
+        );
+        assert_eq!(
+            error.to_string().lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "instruction at address 40 accessed negative address -5",
+                "#0   40 in f_15, relative base 108, frame [0], returns to 31",
+                "#1   31 in f_15, relative base 106, frame [1], returns to 31",
+            ]
+        );
+        assert_eq!(
+            backtrace.frames[4].to_string(),
+            "13 in main, relative base 100"
+        );
+
+        // The decompilation is kept until the program image is written to.
+        let mut machine = IntCodeMachine::new(&PROGRAM);
+        machine.set_backtraces(true);
+        machine.poke(100, 1).unwrap();
+        assert!(machine.decompiled.is_some());
+        machine.poke(43, 1).unwrap();
+        assert!(machine.decompiled.is_none());
+        assert_eq!(machine.run().unwrap_err().backtrace(), Some(backtrace));
+        assert!(machine.decompiled.is_some());
+    }
+
+    #[test]
+    fn test_debugger_backtrace() {
+        let mut debugger = Debugger::new(IntCodeMachine::new(&PROGRAM));
+        let mut output = vec![];
+        debugger
+            .repl("b 15\nc\nc\nbt\n".as_bytes(), &mut output)
+            .unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        // Stopped at the entry of the second call, before its prologue.
+        assert!(text.contains("#0   15 in f_15, relative base 102, returns to 31\n"));
+        assert!(text.contains("#1   31 in f_15, relative base 102, frame [3], returns to 13\n"));
+        assert!(text.contains("#2   13 in main, relative base 100\n"));
+    }
+
+    #[test]
+    fn test_huge_frames() {
+        // f's prologue claims a frame of 10^12 cells; it faults at 12 with the relative base
+        // still that high.
+        let program = vec![
+            109,
+            100,
+            21101,
+            9,
+            0,
+            0,
+            1105,
+            1,
+            10,
+            99,
+            109,
+            1000000000000,
+            1,
+            -5,
+            0,
+            0,
+            99,
+        ];
+        let mut machine = IntCodeMachine::new(&program);
+        assert!(machine.run().is_err());
+        let backtrace = machine.backtrace();
+        assert_eq!(backtrace.frames[0].function.as_deref(), Some("f_10"));
+        assert_eq!(backtrace.frames[0].slots.len(), MAX_SLOTS);
+        assert_eq!(backtrace.frames[0].return_address, Some(9));
+
+        // A relative base near the bottom of the range cannot be unwound.
+        let program = vec![
+            109, 100, 21101, 9, 0, 0, 1105, 1, 10, 99, 109, 1, 1, -5, 0, 0, 99,
+        ];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.relative_base = i64::MIN;
+        machine.instruction = 12;
+        assert_eq!(machine.backtrace().frames.len(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+// Splits the statically reachable code of a program into basic blocks and connects them.
+// Blocks end at jumps and `end`, and start at jump targets and after them.
+//
+// Compiled programs call functions by storing a return address on the stack (e.g.
+// `21102,37,1,0` stores 37 at rel[0]) and then jumping unconditionally, and return with an
+// indirect jump through the stack. The walk recognises the call so the code after it is
+// reached too, and adds a `CallReturn` edge from the call to that code. Jumps whose target
+// is only known at run time get an edge to an unknown destination.
+
+use super::disasm::Instruction;
+use super::{OpCode, ParameterMode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum EdgeKind {
+    FallThrough,
+    Branch,
+    Call,
+    CallReturn,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Edge {
+    pub from: usize,
+    // `None` for a jump through a position or relative operand.
+    pub to: Option<usize>,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+impl BasicBlock {
+    pub fn end(&self) -> usize {
+        self.last().next_address()
+    }
+
+    pub fn last(&self) -> &Instruction {
+        self.instructions
+            .last()
+            .expect("basic blocks are never empty")
+    }
+
+    // The return address when the block ends by calling a function.
+    pub fn call_return(&self) -> Option<usize> {
+        let n = self.instructions.len();
+        match n {
+            0 | 1 => None,
+            _ => call_return(&self.instructions[n - 2], &self.instructions[n - 1]),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+// Add or multiply of two immediates into rel[0], i.e. pushing a constant.
+fn pushed_constant(instruction: &Instruction) -> Option<i64> {
+    let value = match instruction.opcode {
+        OpCode::Add => instruction.operands[0].checked_add(instruction.operands[1])?,
+        OpCode::Multiply => instruction.operands[0].checked_mul(instruction.operands[1])?,
+        _ => return None,
+    };
+
+    match (instruction.modes.as_slice(), instruction.operands[2]) {
+        ([ParameterMode::Immediate, ParameterMode::Immediate, ParameterMode::Relative], 0) => {
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn is_unconditional_jump(instruction: &Instruction) -> bool {
+    instruction.jump_target().is_some() && !instruction.falls_through()
+}
+
+fn call_return(push: &Instruction, jump: &Instruction) -> Option<usize> {
+    match (pushed_constant(push), is_unconditional_jump(jump)) {
+        (Some(address), true) if address >= 0 => Some(address as usize),
+        _ => None,
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &[i64]) -> ControlFlowGraph {
+        let mut covered = vec![false; program.len()];
+        let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut pending = vec![0];
+        leaders.insert(0);
+
+        while let Some(address) = pending.pop() {
+            if address >= program.len() || covered[address] {
+                continue;
+            }
+
+            let instruction = match Instruction::decode(program, address) {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let span = address..instruction.next_address();
+            if covered[span.clone()].iter().any(|c| *c) {
+                continue;
+            }
+            for cell in covered[span].iter_mut() {
+                *cell = true;
+            }
+
+            if let Some(target) = instruction.jump_target() {
+                leaders.insert(target);
+                pending.push(target);
+            }
+
+            match instruction.opcode {
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse | OpCode::End => {
+                    leaders.insert(instruction.next_address());
+                }
+                _ => (),
+            }
+
+            if instruction.falls_through() {
+                pending.push(instruction.next_address());
+            }
+
+            if let Some(jump) = Instruction::decode(program, instruction.next_address()) {
+                if let Some(address) = call_return(&instruction, &jump) {
+                    leaders.insert(address);
+                    pending.push(address);
+                }
+            }
+
+            instructions.insert(address, instruction);
+        }
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for (address, instruction) in instructions.into_iter() {
+            let extend = match blocks.last() {
+                Some(block) => {
+                    block.end() == address
+                        && !leaders.contains(&address)
+                        && !matches!(
+                            block.last().opcode,
+                            OpCode::JumpIfTrue | OpCode::JumpIfFalse | OpCode::End
+                        )
+                }
+                None => false,
+            };
+
+            match (extend, blocks.last_mut()) {
+                (true, Some(block)) => block.instructions.push(instruction),
+                _ => blocks.push(BasicBlock {
+                    start: address,
+                    instructions: vec![instruction],
+                }),
+            }
+        }
+
+        let mut edges = vec![];
+        for block in blocks.iter() {
+            let last = block.last();
+            let from = block.start;
+            let call = block.call_return();
+
+            match last.opcode {
+                OpCode::End => continue,
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse => edges.push(Edge {
+                    from,
+                    to: last.jump_target(),
+                    kind: match call {
+                        Some(_) => EdgeKind::Call,
+                        None => EdgeKind::Branch,
+                    },
+                }),
+                _ => (),
+            }
+
+            if let Some(address) = call {
+                edges.push(Edge {
+                    from,
+                    to: Some(address),
+                    kind: EdgeKind::CallReturn,
+                });
+            }
+
+            if last.falls_through() {
+                edges.push(Edge {
+                    from,
+                    to: Some(last.next_address()),
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+        }
+
+        ControlFlowGraph { blocks, edges }
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks
+            .binary_search_by_key(&start, |block| block.start)
+            .ok()
+            .map(|index| &self.blocks[index])
+    }
+
+    // The block whose instructions cover `address`.
+    pub fn block_at(&self, address: usize) -> Option<&BasicBlock> {
+        let index = match self
+            .blocks
+            .binary_search_by_key(&address, |block| block.start)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let block = &self.blocks[index];
+        match address < block.end() {
+            true => Some(block),
+            false => None,
+        }
+    }
+
+    pub fn successors(&self, start: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.from == start)
+    }
+
+    pub fn predecessors(&self, start: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.to == Some(start))
+    }
+
+    // Graphviz source with one node per block, listing its instructions. Edges into
+    // anything that is not a block go to a single `unknown` node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph cfg {{");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+
+        for block in self.blocks.iter() {
+            let mut label = String::new();
+            for instruction in block.instructions.iter() {
+                let _ = write!(label, "{}: {}\\l", instruction.address, instruction);
+            }
+            let _ = writeln!(dot, "    b{} [label=\"{}\"];", block.start, label);
+        }
+
+        let mut unknown = false;
+        for edge in self.edges.iter() {
+            let to = match edge.to.and_then(|to| self.block(to)) {
+                Some(block) => format!("b{}", block.start),
+                None => {
+                    unknown = true;
+                    String::from("unknown")
+                }
+            };
+            let style = match edge.kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Branch => " [label=\"jump\"]",
+                EdgeKind::Call => " [label=\"call\", style=bold]",
+                EdgeKind::CallReturn => " [label=\"return\", style=dashed]",
+            };
+            let _ = writeln!(dot, "    b{} -> {}{};", edge.from, to, style);
+        }
+
+        if unknown {
+            let _ = writeln!(dot, "    unknown [label=\"?\", shape=diamond];");
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlFlowGraph, Edge, EdgeKind};
+
+    // main calls a function that adds 1 to the cell at 30 unless it is 5, then prints it.
+    const PROGRAM: [i64; 32] = [
+        109, 100, 21101, 9, 0, 0, 1105, 1, 12, 4, 30, 99, 1008, 30, 5, 31, 1005, 31, 23, 101, 1,
+        30, 30, 2106, 0, 0, 0, 0, 0, 0, 5, 0,
+    ];
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let cfg = ControlFlowGraph::new(&PROGRAM);
+        let starts: Vec<usize> = cfg.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 9, 12, 19, 23]);
+        assert_eq!(cfg.block(0).unwrap().call_return(), Some(9));
+        assert_eq!(cfg.block_at(17).unwrap().start, 12);
+        assert!(cfg.block_at(27).is_none());
+
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            cfg.edges,
+            vec![
+                edge(0, Some(12), EdgeKind::Call),
+                edge(0, Some(9), EdgeKind::CallReturn),
+                edge(12, Some(23), EdgeKind::Branch),
+                edge(12, Some(19), EdgeKind::FallThrough),
+                edge(19, Some(23), EdgeKind::FallThrough),
+                edge(23, None, EdgeKind::Branch),
+            ]
+        );
+        assert_eq!(cfg.predecessors(23).count(), 2);
+        assert_eq!(cfg.successors(9).count(), 0);
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let dot = ControlFlowGraph::new(&PROGRAM).to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b12 [label=\"12: eq 30, #5, 31\\l16: jt 31, #23\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b12 [label=\"call\", style=bold];\n"));
+        assert!(dot.contains("    b19 -> b23;\n"));
+        assert!(dot.contains("    b23 -> unknown [label=\"jump\"];\n"));
+        assert!(dot.ends_with("    unknown [label=\"?\", shape=diamond];\n}\n"));
+    }
+}
@@ -0,0 +1,442 @@
+use super::disasm::Instruction;
+use super::{IntCodeError, IntCodeMachine};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+
+// The most cells `x` prints, or instructions `l` lists, at once.
+const MAX_EXAMINE: usize = 4096;
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Output(i64),
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: i64, new: i64 },
+    NeedInput,
+    Halted,
+    Fault(IntCodeError),
+}
+
+pub struct Debugger {
+    machine: IntCodeMachine,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, i64>,
+    outputs: Vec<i64>,
+}
+
+impl Debugger {
+    pub fn new(machine: IntCodeMachine) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            outputs: vec![],
+        }
+    }
+
+    pub fn machine(&self) -> &IntCodeMachine {
+        &self.machine
+    }
+
+    pub fn into_machine(self) -> IntCodeMachine {
+        self.machine
+    }
+
+    pub fn outputs(&self) -> &[i64] {
+        &self.outputs
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) {
+        let value = self.read(address);
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn provide_input(&mut self, input: i64) {
+        self.machine.provide_input(input);
+    }
+
+    pub fn read(&self, address: usize) -> i64 {
+        self.machine.peek(address)
+    }
+
+    pub fn read_range(&self, start: usize, length: usize) -> Vec<i64> {
+        self.machine.peek_range(start, length)
+    }
+
+    pub fn write(&mut self, address: usize, value: i64) -> Result<(), IntCodeError> {
+        self.machine.poke(address, value)?;
+        if let Some(watched) = self.watchpoints.get_mut(&address) {
+            *watched = value;
+        }
+        Ok(())
+    }
+
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.machine.registers.decode(self.machine.instruction)
+    }
+
+    pub fn step(&mut self) -> Stop {
+        let stop = match self.machine.step() {
+            Ok(Some(v)) => {
+                self.outputs.push(v);
+                Stop::Output(v)
+            }
+            Ok(None) => Stop::Stepped,
+            Err(IntCodeError::NeedInput) => return Stop::NeedInput,
+            Err(IntCodeError::ProgramComplete) => return Stop::Halted,
+            Err(e) => return Stop::Fault(e),
+        };
+
+        for (address, last) in self.watchpoints.iter_mut() {
+            let value = self.machine.read(*address);
+            if value != *last {
+                let old = *last;
+                *last = value;
+                return Stop::Watchpoint {
+                    address: *address,
+                    old,
+                    new: value,
+                };
+            }
+        }
+
+        stop
+    }
+
+    // Runs until a breakpoint, a watchpoint, the program halting or running out of input.
+    // Outputs produced along the way are collected in `outputs`.
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            match self.step() {
+                Stop::Stepped | Stop::Output(_) => (),
+                stop => return stop,
+            }
+
+            if self.breakpoints.contains(&self.machine.instruction) {
+                return Stop::Breakpoint(self.machine.instruction);
+            }
+        }
+    }
+
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "(icdb) ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let printed = self.outputs.len();
+
+            match self.command(&words, &mut output) {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                Err(message) => writeln!(output, "error: {}", message)?,
+            }
+
+            for v in self.outputs[printed..].iter() {
+                writeln!(output, "output: {}", v)?;
+            }
+
+            write!(output, "(icdb) ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn command<W: Write>(&mut self, words: &[&str], output: &mut W) -> Result<bool, String> {
+        let numbers = |from: usize| -> Result<Vec<i64>, String> {
+            words[from..]
+                .iter()
+                .map(|w| w.parse::<i64>().map_err(|_| format!("not a number: {}", w)))
+                .collect()
+        };
+        let address = |from: usize| -> Result<usize, String> {
+            match words.get(from) {
+                None => Err(String::from("missing address")),
+                Some(w) => w
+                    .parse::<usize>()
+                    .map_err(|_| format!("not an address: {}", w)),
+            }
+        };
+
+        let write_err = |e: io::Error| e.to_string();
+        match words.first().cloned().unwrap_or("") {
+            "" => (),
+            "q" | "quit" => return Ok(true),
+            "h" | "help" => {
+                writeln!(
+                    output,
+                    "s [n]        step n instructions\n\
+                     c            continue to the next breakpoint, watchpoint, halt or input wait\n\
+                     b <addr>     set a breakpoint        db <addr>  delete it\n\
+                     w <addr>     watch a memory cell     dw <addr>  delete it\n\
+                     i            show machine state\n\
+                     bt           show the call stack\n\
+                     l [n]        list n instructions from the instruction pointer\n\
+                     x <addr> [n] examine n memory cells\n\
+                     in <v>...    queue input values\n\
+                     set <addr> <v>  write to memory\n\
+                     q            quit"
+                )
+                .map_err(write_err)?;
+            }
+            "s" | "step" => {
+                let count = match words.get(1) {
+                    Some(_) => numbers(1)?[0].max(1),
+                    None => 1,
+                };
+
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.step();
+                    match stop {
+                        Stop::Stepped | Stop::Output(_) => (),
+                        _ => break,
+                    }
+                }
+                self.report(&stop, output).map_err(write_err)?;
+            }
+            "c" | "continue" => {
+                let stop = self.resume();
+                self.report(&stop, output).map_err(write_err)?;
+            }
+            "b" | "break" => {
+                let a = address(1)?;
+                self.add_breakpoint(a);
+                writeln!(output, "breakpoint at {}", a).map_err(write_err)?;
+            }
+            "db" => {
+                let a = address(1)?;
+                if !self.remove_breakpoint(a) {
+                    return Err(format!("no breakpoint at {}", a));
+                }
+            }
+            "w" | "watch" => {
+                let a = address(1)?;
+                self.add_watchpoint(a);
+                writeln!(output, "watching {} (currently {})", a, self.read(a))
+                    .map_err(write_err)?;
+            }
+            "dw" => {
+                let a = address(1)?;
+                if !self.remove_watchpoint(a) {
+                    return Err(format!("no watchpoint at {}", a));
+                }
+            }
+            "i" | "info" => self.info(output).map_err(write_err)?,
+            "bt" | "backtrace" => {
+                writeln!(output, "{}", self.machine.backtrace()).map_err(write_err)?
+            }
+            "l" | "list" => {
+                let count = match words.get(1) {
+                    Some(_) => (numbers(1)?[0].max(1) as usize).min(MAX_EXAMINE),
+                    None => 5,
+                };
+
+                let mut address = self.machine.instruction;
+                for _ in 0..count {
+                    match self.machine.registers.decode(address) {
+                        Some(instruction) => {
+                            writeln!(output, "{:>6}: {}", address, instruction)
+                                .map_err(write_err)?;
+                            address = instruction.next_address();
+                        }
+                        None => {
+                            writeln!(output, "{:>6}: .data {}", address, self.read(address))
+                                .map_err(write_err)?;
+                            address += 1;
+                        }
+                    }
+                }
+            }
+            "x" => {
+                let start = address(1)?;
+                let length = match words.get(2) {
+                    Some(_) => address(2)?,
+                    None => 1,
+                };
+                if length > MAX_EXAMINE {
+                    return Err(format!("can examine at most {} cells", MAX_EXAMINE));
+                }
+                if start.checked_add(length).is_none() {
+                    return Err(String::from("range goes past the highest address"));
+                }
+
+                for (row, chunk) in self.read_range(start, length).chunks(8).enumerate() {
+                    let values: Vec<String> = chunk.iter().map(|v| v.to_string()).collect();
+                    writeln!(output, "{:>6}: {}", start + row * 8, values.join(" "))
+                        .map_err(write_err)?;
+                }
+            }
+            "in" | "input" => {
+                for v in numbers(1)? {
+                    self.provide_input(v);
+                }
+            }
+            "set" => {
+                let a = address(1)?;
+                match numbers(2)?.first() {
+                    Some(v) => self.write(a, *v).map_err(|e| e.to_string())?,
+                    None => return Err(String::from("missing value")),
+                }
+            }
+            other => return Err(format!("unknown command: {} (try 'help')", other)),
+        }
+
+        Ok(false)
+    }
+
+    fn report<W: Write>(&self, stop: &Stop, output: &mut W) -> io::Result<()> {
+        match stop {
+            Stop::Stepped | Stop::Output(_) => (),
+            Stop::Breakpoint(a) => writeln!(output, "breakpoint at {}", a)?,
+            Stop::Watchpoint { address, old, new } => {
+                writeln!(output, "watchpoint {}: {} -> {}", address, old, new)?
+            }
+            Stop::NeedInput => writeln!(output, "waiting for input")?,
+            Stop::Halted => writeln!(output, "halted")?,
+            Stop::Fault(e) => {
+                let backtrace = match e.backtrace() {
+                    Some(backtrace) => backtrace.clone(),
+                    None => self.machine.backtrace(),
+                };
+                writeln!(output, "fault: {}\n{}", e.root(), backtrace)?
+            }
+        }
+
+        self.print_current(output)
+    }
+
+    fn print_current<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        match self.current_instruction() {
+            Some(instruction) => writeln!(output, "=> {:>6}: {}", instruction.address, instruction),
+            None => writeln!(
+                output,
+                "=> {:>6}: .data {}",
+                self.machine.instruction,
+                self.read(self.machine.instruction)
+            ),
+        }
+    }
+
+    fn info<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let input: Vec<String> = self.machine.input.iter().map(|v| v.to_string()).collect();
+        let breakpoints: Vec<String> = self.breakpoints.iter().map(|a| a.to_string()).collect();
+        let watchpoints: Vec<String> = self
+            .watchpoints
+            .iter()
+            .map(|(a, v)| format!("{}={}", a, v))
+            .collect();
+
+        writeln!(output, "instruction:   {}", self.machine.instruction)?;
+        writeln!(output, "relative base: {}", self.machine.relative_base)?;
+        writeln!(output, "memory size:   {}", self.machine.registers.len())?;
+        writeln!(output, "input:         [{}]", input.join(", "))?;
+        writeln!(output, "breakpoints:   [{}]", breakpoints.join(", "))?;
+        writeln!(output, "watchpoints:   [{}]", watchpoints.join(", "))?;
+        self.print_current(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, Stop, MAX_EXAMINE};
+    use crate::intcode::{IntCodeError, IntCodeMachine};
+
+    fn quine() -> Vec<i64> {
+        vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let mut debugger = Debugger::new(IntCodeMachine::new(&quine()));
+
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(debugger.step(), Stop::Output(109));
+
+        debugger.add_breakpoint(12);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(12));
+        assert_eq!(debugger.read(100), 1);
+
+        debugger.remove_breakpoint(12);
+        debugger.add_watchpoint(100);
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watchpoint {
+                address: 100,
+                old: 1,
+                new: 2
+            }
+        );
+        assert_eq!(debugger.outputs(), &[109, 1]);
+
+        debugger.remove_watchpoint(100);
+        assert_eq!(debugger.resume(), Stop::Halted);
+        assert_eq!(debugger.outputs(), quine().as_slice());
+    }
+
+    #[test]
+    fn test_input_injection_and_memory_edits() {
+        // Reads a value, adds the cell at 11 to it and prints the result.
+        let program = vec![3, 11, 1, 11, 12, 11, 4, 11, 99, 0, 0, 0, 5];
+        let mut debugger = Debugger::new(IntCodeMachine::new(&program));
+
+        assert_eq!(debugger.resume(), Stop::NeedInput);
+        debugger.provide_input(10);
+        debugger.write(12, 32).unwrap();
+        assert_eq!(debugger.resume(), Stop::Halted);
+        assert_eq!(debugger.outputs(), &[42]);
+
+        debugger.write(8, 98).unwrap();
+        assert_eq!(
+            debugger.step(),
+            Stop::Fault(IntCodeError::InvalidOpcode {
+                address: 8,
+                value: 98
+            })
+        );
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let mut debugger = Debugger::new(IntCodeMachine::new(&quine()));
+        let script = "b 12\nc\ni\nx 100 2\nx 0 18446744073709551615\nx 18446744073709551615 2\n\
+                      set 100 14\ndb 12\nc\nq\n";
+        let mut transcript: Vec<u8> = vec![];
+
+        debugger.repl(script.as_bytes(), &mut transcript).unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+
+        assert!(transcript.contains("breakpoint at 12\n=>     12: jf 101, #0"));
+        assert!(transcript.contains("relative base: 1"));
+        assert!(transcript.contains("   100: 1 0"));
+        assert!(transcript.contains("error: can examine at most 4096 cells\n"));
+        assert!(transcript.contains("error: range goes past the highest address\n"));
+        assert!(transcript.contains("halted"));
+        assert!(transcript.contains("output: 109"));
+        assert_eq!(debugger.outputs(), &[109, 1, 204]);
+
+        let mut debugger = Debugger::new(IntCodeMachine::new(&[99]));
+        let mut transcript: Vec<u8> = vec![];
+        debugger
+            .repl("l 9223372036854775807\nq\n".as_bytes(), &mut transcript)
+            .unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        assert_eq!(transcript.matches(": .data 0\n").count(), MAX_EXAMINE - 1);
+    }
+}
@@ -0,0 +1,608 @@
+// Turns a program back into structured pseudo-code, using the calling convention that
+// compiled Intcode programs follow:
+//
+//   caller: rel[1..] = arguments; rel[0] = return address; jump f
+//   f:      rbo #N; ...; rbo #-N; jump rel[0]
+//
+// Inside `f` the return address is at rel[-N] and the cells after it are its frame. The
+// ones callers write are arguments (`a1`, `a2`, ...) and the rest are locals (`l1`, ...).
+// Results come back in the first argument slot, which the caller sees as `out1`.
+//
+// Forward branches become `if`/`else` and backward branches `do`/`while` where the blocks
+// nest; anything else falls back to `goto`.
+
+use super::cfg::{BasicBlock, ControlFlowGraph, EdgeKind};
+use super::disasm::Instruction;
+use super::{OpCode, ParameterMode};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub entry: usize,
+    // The `N` of the `rbo #N` prologue, or 0 for `main` and functions without one.
+    pub frame_size: usize,
+    pub arguments: usize,
+    pub locals: usize,
+    pub blocks: Vec<usize>,
+    // Addresses of the jumps that call this function, and where each call returns to.
+    pub call_sites: Vec<usize>,
+    pub return_addresses: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Decompiled {
+    pub functions: Vec<Function>,
+    cfg: ControlFlowGraph,
+}
+
+impl Decompiled {
+    pub fn function(&self, entry: usize) -> Option<&Function> {
+        self.functions.iter().find(|f| f.entry == entry)
+    }
+
+    // The function whose code covers `address`.
+    pub fn function_at(&self, address: usize) -> Option<&Function> {
+        let block = self.cfg.block_at(address)?;
+        self.functions
+            .iter()
+            .find(|f| f.blocks.binary_search(&block.start).is_ok())
+    }
+}
+
+fn prologue(block: &BasicBlock) -> usize {
+    let first = &block.instructions[0];
+    match first.opcode {
+        OpCode::RelativeBaseOffset => match (first.modes[0], first.operands[0]) {
+            (ParameterMode::Immediate, n) if n > 0 => n as usize,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+// An always-taken jump through rel[0].
+pub(crate) fn is_return(instruction: &Instruction) -> bool {
+    match instruction.opcode {
+        OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+            !instruction.falls_through()
+                && instruction.modes[1] == ParameterMode::Relative
+                && instruction.operands[1] == 0
+        }
+        _ => false,
+    }
+}
+
+// The rel[k] slot an instruction writes, if any.
+fn written_slot(instruction: &Instruction) -> Option<i64> {
+    let index = instruction.opcode.write_parameter()? as usize - 1;
+    match instruction.modes[index] {
+        ParameterMode::Relative => Some(instruction.operands[index]),
+        _ => None,
+    }
+}
+
+// The argument stores folded into the call that ends `block`, by slot, and the index of the
+// first instruction that belongs to the call.
+fn call_arguments(block: &BasicBlock) -> (BTreeMap<i64, usize>, usize) {
+    let mut arguments = BTreeMap::new();
+    let mut first = block.instructions.len() - 2;
+    while first > 0 {
+        let instruction = &block.instructions[first - 1];
+        match written_slot(instruction) {
+            Some(k) if k >= 1 && !arguments.contains_key(&k) => {
+                arguments.insert(k, first - 1);
+                first -= 1;
+            }
+            _ => break,
+        }
+    }
+    (arguments, first)
+}
+
+pub fn decompile(program: &[i64]) -> Decompiled {
+    let cfg = ControlFlowGraph::new(program);
+
+    let mut entries: BTreeSet<usize> = BTreeSet::new();
+    let mut calls: BTreeMap<usize, Vec<(usize, usize, usize)>> = BTreeMap::new();
+    for block in cfg.blocks.iter() {
+        let call = cfg
+            .successors(block.start)
+            .find(|edge| edge.kind == EdgeKind::Call)
+            .and_then(|edge| edge.to);
+        if let (Some(target), Some(return_address)) = (call, block.call_return()) {
+            let (arguments, _) = call_arguments(block);
+            let count = arguments.keys().max().copied().unwrap_or(0) as usize;
+            entries.insert(target);
+            calls
+                .entry(target)
+                .or_default()
+                .push((block.last().address, return_address, count));
+        }
+    }
+    entries.remove(&0);
+
+    let mut functions = vec![];
+    for entry in std::iter::once(0).chain(entries.iter().copied()) {
+        // `main` starts by moving the relative base to the stack, which is not a frame.
+        let frame_size = match entry {
+            0 => 0,
+            _ => cfg.block(entry).map(prologue).unwrap_or(0),
+        };
+        let sites = calls.remove(&entry).unwrap_or_default();
+        let arguments = match sites.iter().map(|s| s.2).max() {
+            Some(count) if frame_size > 0 => count.min(frame_size - 1),
+            _ => frame_size.saturating_sub(1),
+        };
+
+        // Everything reachable without following a call into another function.
+        let mut blocks = BTreeSet::new();
+        let mut pending = VecDeque::from(vec![entry]);
+        while let Some(start) = pending.pop_front() {
+            if cfg.block(start).is_none() || !blocks.insert(start) {
+                continue;
+            }
+            for edge in cfg.successors(start) {
+                match (edge.kind, edge.to) {
+                    (EdgeKind::Call, _) | (_, None) => (),
+                    (_, Some(to)) if entries.contains(&to) || to == 0 => (),
+                    (_, Some(to)) => pending.push_back(to),
+                }
+            }
+        }
+
+        functions.push(Function {
+            name: match entry {
+                0 => String::from("main"),
+                _ => format!("f_{}", entry),
+            },
+            entry,
+            frame_size,
+            arguments,
+            locals: frame_size.saturating_sub(1) - arguments,
+            blocks: blocks.into_iter().collect(),
+            call_sites: sites.iter().map(|s| s.0).collect(),
+            return_addresses: sites.iter().map(|s| s.1).collect(),
+        });
+    }
+
+    Decompiled { functions, cfg }
+}
+
+enum Line {
+    Label(usize),
+    Text(usize, String),
+}
+
+struct Writer<'a> {
+    decompiled: &'a Decompiled,
+    function: &'a Function,
+    blocks: Vec<&'a BasicBlock>,
+    lines: Vec<Line>,
+    gotos: BTreeSet<usize>,
+}
+
+impl<'a> Writer<'a> {
+    fn slot(&self, offset: i64) -> String {
+        let n = self.function.frame_size as i64;
+        match offset {
+            k if n > 0 && k == -n => String::from("ret"),
+            k if k > -n && k < 0 => match (k + n) as usize {
+                j if j <= self.function.arguments => format!("a{}", j),
+                j => format!("l{}", j - self.function.arguments),
+            },
+            k if k > 0 => format!("out{}", k),
+            k => format!("rel[{}]", k),
+        }
+    }
+
+    fn operand(&self, instruction: &Instruction, index: usize) -> String {
+        let value = instruction.operands[index];
+        match instruction.modes[index] {
+            ParameterMode::Position => format!("mem[{}]", value),
+            ParameterMode::Immediate => value.to_string(),
+            ParameterMode::Relative => self.slot(value),
+        }
+    }
+
+    // The value an instruction writes, with `x + 0` and `x * 1` reduced to `x`.
+    fn value(&self, instruction: &Instruction) -> String {
+        let identity = match instruction.opcode {
+            OpCode::Add => Some(0),
+            OpCode::Multiply => Some(1),
+            _ => None,
+        };
+        let immediate = |i: usize| match instruction.modes[i] {
+            ParameterMode::Immediate => Some(instruction.operands[i]),
+            _ => None,
+        };
+        if identity.is_some() && immediate(0) == identity {
+            return self.operand(instruction, 1);
+        }
+        if identity.is_some() && immediate(1) == identity {
+            return self.operand(instruction, 0);
+        }
+
+        if instruction.opcode == OpCode::Add {
+            if let Some(v) = immediate(1).filter(|v| *v < 0) {
+                let left = self.operand(instruction, 0);
+                return format!("{} - {}", left, v.unsigned_abs());
+            }
+        }
+
+        let operator = match instruction.opcode {
+            OpCode::Add => "+",
+            OpCode::Multiply => "*",
+            OpCode::LessThan => "<",
+            OpCode::Equals => "==",
+            _ => return String::from("input()"),
+        };
+        format!(
+            "{} {} {}",
+            self.operand(instruction, 0),
+            operator,
+            self.operand(instruction, 1)
+        )
+    }
+
+    fn statement(&self, instruction: &Instruction) -> String {
+        match (instruction.opcode, instruction.opcode.write_parameter()) {
+            (OpCode::Output, _) => format!("output({});", self.operand(instruction, 0)),
+            (OpCode::RelativeBaseOffset, _) => {
+                format!("relative_base += {};", self.operand(instruction, 0))
+            }
+            (_, Some(target)) => format!(
+                "{} = {};",
+                self.operand(instruction, target as usize - 1),
+                self.value(instruction)
+            ),
+            _ => format!("// {}", instruction),
+        }
+    }
+
+    // `c` when the branch is taken if `taken`, `!c` otherwise.
+    fn condition(&self, branch: &Instruction, taken: bool) -> String {
+        let value = self.operand(branch, 0);
+        match (branch.opcode == OpCode::JumpIfTrue) == taken {
+            true => value,
+            false => format!("!{}", value),
+        }
+    }
+
+    fn index(&self, start: usize) -> Option<usize> {
+        self.blocks.iter().position(|b| b.start == start)
+    }
+
+    fn line(&mut self, depth: usize, text: String) {
+        self.lines.push(Line::Text(depth, text));
+    }
+
+    fn goto(&mut self, depth: usize, condition: Option<String>, target: usize) {
+        self.gotos.insert(target);
+        match condition {
+            Some(c) => self.line(depth, format!("if ({}) goto L_{};", c, target)),
+            None => self.line(depth, format!("goto L_{};", target)),
+        }
+    }
+
+    // Straight-line statements of a block, leaving out the prologue, epilogue, call
+    // sequence and terminating jump. Returns whether the block ends in a call.
+    fn body(&mut self, index: usize, depth: usize) -> bool {
+        let block = self.blocks[index];
+        let instructions = &block.instructions;
+        let n = self.function.frame_size as i64;
+        let call = block.call_return().is_some();
+        let (arguments, call_start) = match call {
+            true => call_arguments(block),
+            false => (BTreeMap::new(), instructions.len()),
+        };
+        let mut end = match instructions.last().map(|i| i.opcode) {
+            _ if call => call_start,
+            Some(OpCode::JumpIfTrue) | Some(OpCode::JumpIfFalse) | Some(OpCode::End) => {
+                instructions.len() - 1
+            }
+            _ => instructions.len(),
+        };
+        let epilogue = |i: &Instruction| {
+            i.opcode == OpCode::RelativeBaseOffset
+                && i.modes[0] == ParameterMode::Immediate
+                && i.operands[0] == -n
+        };
+        if n > 0 && end > 0 && is_return(block.last()) && epilogue(&instructions[end - 1]) {
+            end -= 1;
+        }
+        let start = match block.start == self.function.entry && n > 0 {
+            true => 1,
+            false => 0,
+        };
+
+        for instruction in instructions[start.min(end)..end].iter() {
+            let text = self.statement(instruction);
+            self.line(depth, text);
+        }
+
+        if call {
+            let target = block.last().jump_target().unwrap_or(0);
+            let name = match self.decompiled.function(target) {
+                Some(f) => f.name.clone(),
+                None => format!("f_{}", target),
+            };
+            let count = arguments.keys().max().copied().unwrap_or(0);
+            let values: Vec<String> = (1..=count)
+                .map(|k| match arguments.get(&k) {
+                    Some(i) => self.value(&instructions[*i]),
+                    None => self.slot(k),
+                })
+                .collect();
+            self.line(depth, format!("call {}({});", name, values.join(", ")));
+        }
+        call
+    }
+
+    // Emits blocks `lo..hi`. With `open_end`, the unconditional jump ending the last block
+    // is left out because the caller has already turned it into structure.
+    fn range(&mut self, lo: usize, hi: usize, depth: usize, open_end: bool) {
+        let mut i = lo;
+        while i < hi {
+            let start = self.blocks[i].start;
+
+            // The farthest conditional branch back to this block closes a loop.
+            let back = (i..hi).rev().find(|k| {
+                let last = self.blocks[*k].last();
+                last.falls_through() && last.jump_target() == Some(start)
+            });
+            if let Some(k) = back {
+                self.lines.push(Line::Label(start));
+                self.line(depth, String::from("do {"));
+                self.range(i, k, depth + 1, false);
+                if k > i {
+                    self.lines.push(Line::Label(self.blocks[k].start));
+                }
+                self.body(k, depth + 1);
+                let condition = self.condition(self.blocks[k].last(), true);
+                self.line(depth, format!("}} while ({});", condition));
+                i = k + 1;
+                continue;
+            }
+
+            self.lines.push(Line::Label(start));
+            let call = self.body(i, depth);
+            let block = self.blocks[i];
+            let last = block.last();
+            let next = self.blocks.get(i + 1).map(|b| b.start);
+
+            if call {
+                if let Some(address) = block.call_return() {
+                    if Some(address) != next {
+                        self.goto(depth, None, address);
+                    }
+                }
+                i += 1;
+                continue;
+            }
+
+            match last.opcode {
+                OpCode::End => self.line(depth, String::from("halt;")),
+                _ if is_return(last) => self.line(depth, String::from("return;")),
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                    let target = last.jump_target();
+                    let m = target.and_then(|t| self.index(t));
+                    match (last.falls_through(), target, m) {
+                        // Skips forward over blocks that nest inside this range.
+                        (true, Some(_), Some(m)) if m > i && m <= hi => {
+                            let condition = self.condition(last, false);
+                            self.line(depth, format!("if ({}) {{", condition));
+
+                            let join = self.blocks[m - 1]
+                                .last()
+                                .jump_target()
+                                .filter(|_| {
+                                    m - 1 > i
+                                        && !self.blocks[m - 1].last().falls_through()
+                                        && self.blocks[m - 1].call_return().is_none()
+                                })
+                                .and_then(|u| self.index(u))
+                                .filter(|n| *n > m && *n <= hi);
+                            match join {
+                                Some(n) => {
+                                    self.range(i + 1, m, depth + 1, true);
+                                    self.line(depth, String::from("} else {"));
+                                    self.range(m, n, depth + 1, false);
+                                    self.line(depth, String::from("}"));
+                                    i = n;
+                                }
+                                None => {
+                                    self.range(i + 1, m, depth + 1, false);
+                                    self.line(depth, String::from("}"));
+                                    i = m;
+                                }
+                            }
+                            continue;
+                        }
+                        (true, Some(t), _) => {
+                            let condition = self.condition(last, true);
+                            self.goto(depth, Some(condition), t);
+                        }
+                        (false, Some(t), _) => {
+                            if !(open_end && i + 1 == hi) && Some(t) != next {
+                                self.goto(depth, None, t);
+                            }
+                        }
+                        (true, None, _) => {
+                            let condition = self.condition(last, true);
+                            let target = self.operand(last, 1);
+                            self.line(depth, format!("if ({}) goto *{};", condition, target));
+                        }
+                        (false, None, _) => {
+                            let target = self.operand(last, 1);
+                            self.line(depth, format!("goto *{};", target));
+                        }
+                    }
+                }
+                _ => {
+                    if Some(last.next_address()) != next {
+                        self.goto(depth, None, last.next_address());
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (number, function) in self.functions.iter().enumerate() {
+            if number > 0 {
+                writeln!(f)?;
+            }
+
+            let mut writer = Writer {
+                decompiled: self,
+                function,
+                blocks: function
+                    .blocks
+                    .iter()
+                    .filter_map(|start| self.cfg.block(*start))
+                    .collect(),
+                lines: vec![],
+                gotos: BTreeSet::new(),
+            };
+            let count = writer.blocks.len();
+            writer.range(0, count, 1, false);
+
+            if function.entry != 0 {
+                let callers: Vec<String> =
+                    function.call_sites.iter().map(|a| a.to_string()).collect();
+                writeln!(
+                    f,
+                    "// {}: frame of {} cells, {} arguments, {} locals; called from {}",
+                    function.entry,
+                    function.frame_size,
+                    function.arguments,
+                    function.locals,
+                    callers.join(", ")
+                )?;
+            }
+            let parameters: Vec<String> = (1..=function.arguments)
+                .map(|j| format!("a{}", j))
+                .collect();
+            writeln!(f, "fn {}({}) {{", function.name, parameters.join(", "))?;
+            for line in writer.lines.iter() {
+                match line {
+                    Line::Label(start) if writer.gotos.contains(start) => {
+                        writeln!(f, "L_{}:", start)?
+                    }
+                    Line::Label(_) => (),
+                    Line::Text(depth, text) => writeln!(f, "{}{}", "    ".repeat(*depth), text)?,
+                }
+            }
+            writeln!(f, "}}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decompile;
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    // main reads a number and passes it to a function that doubles it until it is at
+    // least 100, then prints the result.
+    const PROGRAM: [i64; 44] = [
+        109, 200, 3, 100, 21001, 100, 0, 1, 21101, 15, 0, 0, 1105, 1, 19, 204, 1, 99, 0, 109, 2,
+        1207, -1, 100, 18, 1006, 18, 39, 21202, -1, 2, -1, 1207, -1, 100, 18, 1005, 18, 28, 109,
+        -2, 2106, 0, 0,
+    ];
+
+    #[test]
+    fn test_functions() {
+        let mut machine = IntCodeMachine::new(&PROGRAM);
+        machine.provide_input(7);
+        assert_eq!(machine.run(), Ok(RunState::Output(112)));
+
+        let decompiled = decompile(&PROGRAM);
+        assert_eq!(decompiled.functions.len(), 2);
+        let function = decompiled.function(19).unwrap();
+        assert_eq!(function.name, "f_19");
+        assert_eq!(
+            (function.frame_size, function.arguments, function.locals),
+            (2, 1, 0)
+        );
+        assert_eq!(function.blocks, vec![19, 28, 39]);
+        assert_eq!(function.call_sites, vec![12]);
+        assert_eq!(function.return_addresses, vec![15]);
+        assert_eq!(decompiled.functions[0].blocks, vec![0, 15]);
+    }
+
+    #[test]
+    fn test_pseudo_code() {
+        assert_eq!(
+            decompile(&PROGRAM).to_string(),
+            "fn main() {
+    relative_base += 200;
+    mem[100] = input();
+    call f_19(mem[100]);
+    output(out1);
+    halt;
+}
+
+// 19: frame of 2 cells, 1 arguments, 0 locals; called from 12
+fn f_19(a1) {
+    mem[18] = a1 < 100;
+    if (mem[18]) {
+        do {
+            a1 = a1 * 2;
+            mem[18] = a1 < 100;
+        } while (mem[18]);
+    }
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_gotos() {
+        // Counts down from 3. The loop closes with an unconditional jump, which is left as
+        // a `goto`.
+        let program = vec![
+            4, 20, 1001, 20, -1, 20, 1006, 20, 15, 1105, 1, 0, 0, 0, 0, 99, 0, 0, 0, 0, 3,
+        ];
+        let text = decompile(&program).to_string();
+        assert_eq!(
+            text,
+            "fn main() {
+L_0:
+    output(mem[20]);
+    mem[20] = mem[20] - 1;
+    if (mem[20]) {
+        goto L_0;
+    }
+    halt;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_function_without_prologue() {
+        // The called function is just `end`, so it has no operands to look at.
+        let text = decompile(&[21101, 7, 0, 0, 1105, 1, 8, 99, 99]).to_string();
+        assert!(text.contains("fn f_8() {\n    halt;\n}\n"));
+
+        let mut machine = IntCodeMachine::new(&[4, -1, 21101, 9, 0, 0, 1105, 1, 10, 99, 99]);
+        machine.set_backtraces(true);
+        let error = machine.run().unwrap_err();
+        assert_eq!(
+            error.root(),
+            &IntCodeError::NegativeAddress {
+                address: 0,
+                target: -1,
+            }
+        );
+    }
+}
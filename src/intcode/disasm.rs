@@ -0,0 +1,299 @@
+use super::{OpCode, ParameterMode};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub address: usize,
+    pub raw: i64,
+    pub opcode: OpCode,
+    pub modes: Vec<ParameterMode>,
+    pub operands: Vec<i64>,
+}
+
+impl Instruction {
+    // Decodes the instruction at `address` the same way `IntCodeMachine` would, or returns
+    // None if the cell is not a valid instruction or its operands run off the end of memory.
+    pub fn decode(program: &[i64], address: usize) -> Option<Instruction> {
+        let raw = *program.get(address)?;
+        let opcode = OpCode::try_from_instruction(raw)?;
+
+        let mut all_modes = vec![];
+        for number in 1..=3 {
+            all_modes.push(ParameterMode::try_from_instruction_and_number(raw, number)?);
+        }
+
+        let count = opcode.parameter_count();
+        let operands = program.get(address + 1..address + 1 + count)?.to_vec();
+        all_modes.truncate(count);
+
+        Some(Instruction {
+            address,
+            raw,
+            opcode,
+            modes: all_modes,
+            operands,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        1 + self.operands.len()
+    }
+
+    pub fn next_address(&self) -> usize {
+        self.address + self.size()
+    }
+
+    // The jump destination, when it is known without running the program.
+    pub fn jump_target(&self) -> Option<usize> {
+        match self.opcode {
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => match self.modes[1] {
+                ParameterMode::Immediate if self.operands[1] >= 0 => {
+                    Some(self.operands[1] as usize)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Whether execution can continue at `next_address`, e.g. `1106,0,x` always jumps.
+    pub fn falls_through(&self) -> bool {
+        match self.opcode {
+            OpCode::End => false,
+            OpCode::JumpIfTrue => {
+                !(self.modes[0] == ParameterMode::Immediate && self.operands[0] != 0)
+            }
+            OpCode::JumpIfFalse => {
+                !(self.modes[0] == ParameterMode::Immediate && self.operands[0] == 0)
+            }
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for (i, (mode, operand)) in self.modes.iter().zip(self.operands.iter()).enumerate() {
+            write!(f, "{}", if i == 0 { " " } else { ", " })?;
+            match mode {
+                ParameterMode::Position => write!(f, "{}", operand)?,
+                ParameterMode::Immediate => write!(f, "#{}", operand)?,
+                ParameterMode::Relative => write!(f, "rel[{}]", operand)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Entry {
+    Code(Instruction),
+    Data { address: usize, value: i64 },
+}
+
+impl Entry {
+    pub fn address(&self) -> usize {
+        match self {
+            Entry::Code(instruction) => instruction.address,
+            Entry::Data { address, .. } => *address,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entry::Code(instruction) => {
+                let raw: Vec<String> = std::iter::once(instruction.raw)
+                    .chain(instruction.operands.iter().cloned())
+                    .map(|v| v.to_string())
+                    .collect();
+                write!(
+                    f,
+                    "{:>6}: {:<32} {}",
+                    instruction.address,
+                    raw.join(","),
+                    instruction
+                )
+            }
+            Entry::Data { address, value } => {
+                write!(f, "{:>6}: {:<32} .data {}", address, value, value)
+            }
+        }
+    }
+}
+
+pub struct Listing {
+    pub entries: Vec<Entry>,
+}
+
+impl Listing {
+    pub fn entry_at(&self, address: usize) -> Option<&Entry> {
+        match self
+            .entries
+            .binary_search_by_key(&address, |entry| entry.address())
+        {
+            Ok(index) => Some(&self.entries[index]),
+            Err(0) => None,
+            Err(index) => match &self.entries[index - 1] {
+                Entry::Code(instruction) if address < instruction.next_address() => {
+                    Some(&self.entries[index - 1])
+                }
+                _ => None,
+            },
+        }
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::Code(instruction) => Some(instruction),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Walks every instruction reachable from address 0, following jumps whose targets are
+// immediate. Everything that is never reached, or cannot be decoded, is listed as data.
+pub fn disassemble(program: &[i64]) -> Listing {
+    let mut covered = vec![false; program.len()];
+    let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut pending = vec![0];
+
+    while let Some(address) = pending.pop() {
+        if address >= program.len() || covered[address] {
+            continue;
+        }
+
+        let instruction = match Instruction::decode(program, address) {
+            Some(i) => i,
+            None => continue,
+        };
+
+        let span = address..instruction.next_address();
+        if covered[span.clone()].iter().any(|c| *c) {
+            continue;
+        }
+
+        for cell in covered[span].iter_mut() {
+            *cell = true;
+        }
+
+        if let Some(target) = instruction.jump_target() {
+            pending.push(target);
+        }
+
+        if instruction.falls_through() {
+            pending.push(instruction.next_address());
+        }
+
+        instructions.insert(address, instruction);
+    }
+
+    let mut entries = vec![];
+    let mut address = 0;
+    while address < program.len() {
+        match instructions.remove(&address) {
+            Some(instruction) => {
+                address = instruction.next_address();
+                entries.push(Entry::Code(instruction));
+            }
+            None => {
+                entries.push(Entry::Data {
+                    address,
+                    value: program[address],
+                });
+                address += 1;
+            }
+        }
+    }
+
+    Listing { entries }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, Entry};
+
+    #[test]
+    fn test_disassemble_quine() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+
+        let listing = disassemble(&program);
+        let lines: Vec<String> = listing
+            .entries
+            .iter()
+            .map(|e| {
+                e.to_string()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "0: 109,1 rbo #1",
+                "2: 204,-1 out rel[-1]",
+                "4: 1001,100,1,100 add 100, #1, 100",
+                "8: 1008,100,16,101 eq 100, #16, 101",
+                "12: 1006,101,0 jf 101, #0",
+                "15: 99 end",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_separates_data() {
+        // Unconditional jump over two data cells, then an undecodable opcode after `end`.
+        let program = vec![1105, 1, 5, 12345, 7, 4, 3, 99, 55, 1];
+        let listing = disassemble(&program);
+
+        let kinds: Vec<(usize, bool)> = listing
+            .entries
+            .iter()
+            .map(|e| (e.address(), matches!(e, Entry::Code(_))))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (0, true),
+                (3, false),
+                (4, false),
+                (5, true),
+                (7, true),
+                (8, false),
+                (9, false),
+            ]
+        );
+
+        assert_eq!(listing.entry_at(6), listing.entry_at(5));
+        assert!(listing.entry_at(3).is_some());
+        assert!(listing.entry_at(10).is_none());
+    }
+
+    #[test]
+    fn test_disassemble_never_panics_on_garbage() {
+        let program = vec![3, 1, 22222, -5, 1234599, 1008];
+        let listing = disassemble(&program);
+
+        assert_eq!(listing.instructions().count(), 1);
+        assert_eq!(listing.entries.len(), 5);
+    }
+}
@@ -0,0 +1,263 @@
+// Devices an `IntCodeMachine` can be wired to. Values queued with `provide_input` are always
+// consumed first; the input source is only asked once that queue is empty. While an output
+// sink is attached, outputs are sent to it and the machine keeps running.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+pub trait InputSource {
+    // `Ok(None)` means no input is available, which leaves the machine awaiting input.
+    fn read(&mut self) -> io::Result<Option<i64>>;
+}
+
+pub trait OutputSink {
+    fn write(&mut self, value: i64) -> io::Result<()>;
+}
+
+impl<F: FnMut() -> Option<i64>> InputSource for F {
+    fn read(&mut self) -> io::Result<Option<i64>> {
+        Ok(self())
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for F {
+    fn write(&mut self, value: i64) -> io::Result<()> {
+        self(value);
+        Ok(())
+    }
+}
+
+pub struct IterInput<I: Iterator<Item = i64>> {
+    values: I,
+}
+
+impl<I: Iterator<Item = i64>> IterInput<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(values: T) -> IterInput<I> {
+        IterInput {
+            values: values.into_iter(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = i64>> InputSource for IterInput<I> {
+    fn read(&mut self) -> io::Result<Option<i64>> {
+        Ok(self.values.next())
+    }
+}
+
+// Blocks until a value arrives. Once every sender is gone the machine awaits input.
+impl InputSource for Receiver<i64> {
+    fn read(&mut self) -> io::Result<Option<i64>> {
+        Ok(self.recv().ok())
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "output channel disconnected")
+}
+
+impl OutputSink for Sender<i64> {
+    fn write(&mut self, value: i64) -> io::Result<()> {
+        self.send(value).map_err(|_| disconnected())
+    }
+}
+
+impl OutputSink for SyncSender<i64> {
+    fn write(&mut self, value: i64) -> io::Result<()> {
+        self.send(value).map_err(|_| disconnected())
+    }
+}
+
+// Reads integers separated by commas or whitespace, across as many lines as needed.
+pub struct LineReader<R: BufRead> {
+    reader: R,
+    pending: VecDeque<i64>,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(reader: R) -> LineReader<R> {
+        LineReader {
+            reader,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: BufRead> InputSource for LineReader<R> {
+    fn read(&mut self) -> io::Result<Option<i64>> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            for token in line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|t| !t.is_empty())
+            {
+                let value = token.parse::<i64>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("'{}' is not a number", token),
+                    )
+                })?;
+                self.pending.push_back(value);
+            }
+        }
+
+        Ok(self.pending.pop_front())
+    }
+}
+
+// Writes one value per line, flushing each so interactive readers see it immediately.
+pub struct LineWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LineWriter<W> {
+    pub fn new(writer: W) -> LineWriter<W> {
+        LineWriter { writer }
+    }
+}
+
+impl<W: Write> OutputSink for LineWriter<W> {
+    fn write(&mut self, value: i64) -> io::Result<()> {
+        writeln!(self.writer, "{}", value)?;
+        self.writer.flush()
+    }
+}
+
+pub fn stdin() -> LineReader<BufReader<Stdin>> {
+    LineReader::new(BufReader::new(io::stdin()))
+}
+
+pub fn stdout() -> LineWriter<Stdout> {
+    LineWriter::new(io::stdout())
+}
+
+// The machine's attachment points. Like tracers, devices are not carried over to clones.
+#[derive(Default)]
+pub(crate) struct Devices {
+    pub(crate) input: Option<Box<dyn InputSource + Send>>,
+    pub(crate) output: Option<Box<dyn OutputSink + Send>>,
+}
+
+impl Clone for Devices {
+    fn clone(&self) -> Self {
+        Devices::default()
+    }
+}
+
+impl fmt::Debug for Devices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Devices {{ input: {}, output: {} }}",
+            self.input.is_some(),
+            self.output.is_some()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IterInput, LineReader, LineWriter};
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    // Adds pairs of inputs until the input runs out.
+    fn adder() -> Vec<i64> {
+        vec![3, 100, 3, 101, 1, 100, 101, 102, 4, 102, 1105, 1, 0]
+    }
+
+    #[test]
+    fn test_iterator_and_closure_devices() {
+        let outputs = Arc::new(Mutex::new(vec![]));
+        let recorder = outputs.clone();
+
+        let mut machine = IntCodeMachine::new(&adder());
+        machine.set_input_source(IterInput::new(vec![1, 2, 30, 40]));
+        machine.set_output_sink(move |v| recorder.lock().unwrap().push(v));
+        assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+        assert_eq!(*outputs.lock().unwrap(), vec![3, 70]);
+
+        // Queued input still takes priority, and outputs come back once the sink is gone.
+        assert!(machine.clear_output_sink().is_some());
+        machine.provide_input(5);
+        machine.provide_input(6);
+        assert_eq!(machine.get_output(), Some(11));
+
+        let mut counter = 0;
+        machine.set_input_source(move || {
+            counter += 1;
+            Some(counter)
+        });
+        assert_eq!(machine.run(), Ok(RunState::Output(3)));
+        assert_eq!(machine.run(), Ok(RunState::Output(7)));
+    }
+
+    #[test]
+    fn test_channel_devices() {
+        let (input, receiver) = channel();
+        let (sender, output) = channel();
+
+        let mut machine = IntCodeMachine::new(&adder());
+        machine.set_input_source(receiver);
+        machine.set_output_sink(sender);
+        let worker = thread::spawn(move || machine.run());
+
+        for v in 1..=4 {
+            input.send(v).unwrap();
+        }
+        assert_eq!(output.recv(), Ok(3));
+        assert_eq!(output.recv(), Ok(7));
+        drop(input);
+        assert_eq!(worker.join().unwrap(), Ok(RunState::AwaitingInput));
+
+        let (sender, output) = channel();
+        drop(output);
+        let mut machine = IntCodeMachine::new(&adder());
+        machine.set_output_sink(sender);
+        machine.provide_input(1);
+        machine.provide_input(1);
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::Device {
+                address: 8,
+                message: String::from("output channel disconnected"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_line_devices() {
+        let text = "1, 2\n\n30 40\nx\n";
+        let buffer = Arc::new(Mutex::new(vec![]));
+
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Shared {
+            fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(data)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut machine = IntCodeMachine::new(&adder());
+        machine.set_input_source(LineReader::new(text.as_bytes()));
+        machine.set_output_sink(LineWriter::new(Shared(buffer.clone())));
+        match machine.run() {
+            Err(IntCodeError::Device { address, message }) => {
+                assert_eq!(address, 0);
+                assert_eq!(message, "'x' is not a number");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(buffer.lock().unwrap().as_slice(), b"3\n70\n");
+    }
+}
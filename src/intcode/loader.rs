@@ -0,0 +1,125 @@
+// Reads Intcode programs from puzzle inputs. Values may be separated by commas, whitespace
+// or newlines, and `#` starts a comment that runs to the end of the line.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    // `index` counts values from 0, so it is also the address the value would load at.
+    InvalidToken {
+        index: usize,
+        line: usize,
+        token: String,
+    },
+    Empty,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::InvalidToken { index, line, token } => write!(
+                f,
+                "value {} on line {} is not a number: '{}'",
+                index, line, token
+            ),
+            LoadError::Empty => write!(f, "program is empty"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+pub fn parse_program(text: &str) -> Result<Vec<i64>, LoadError> {
+    let mut program = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        for token in code
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+        {
+            match token.parse::<i64>() {
+                Ok(v) => program.push(v),
+                Err(_) => {
+                    return Err(LoadError::InvalidToken {
+                        index: program.len(),
+                        line: number + 1,
+                        token: token.to_owned(),
+                    })
+                }
+            }
+        }
+    }
+
+    if program.is_empty() {
+        return Err(LoadError::Empty);
+    }
+
+    Ok(program)
+}
+
+pub fn read_program<R: Read>(mut reader: R) -> Result<Vec<i64>, LoadError> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    parse_program(&text)
+}
+
+pub fn load_program<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, LoadError> {
+    read_program(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_program, parse_program, read_program, LoadError};
+    use crate::intcode::temp::TempFile;
+
+    #[test]
+    fn test_tolerant_parsing() {
+        let text = "# day 2 example\n1,9,10,3,\n  2,3,11,0, # multiply\n99\n30 40 50\r\n";
+        assert_eq!(
+            parse_program(text).unwrap(),
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]
+        );
+        assert_eq!(
+            read_program("104,-7,99\n".as_bytes()).unwrap(),
+            vec![104, -7, 99]
+        );
+
+        let file = TempFile::new("loader_test.txt");
+        std::fs::write(file.path(), "3,0,4,0,99\n\n").unwrap();
+        assert_eq!(load_program(file.path()).unwrap(), vec![3, 0, 4, 0, 99]);
+    }
+
+    #[test]
+    fn test_errors() {
+        match parse_program("1,2,3\n4,x5,6") {
+            Err(LoadError::InvalidToken { index, line, token }) => {
+                assert_eq!((index, line, token.as_str()), (4, 2, "x5"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(matches!(
+            parse_program(" # nothing\n"),
+            Err(LoadError::Empty)
+        ));
+        assert!(matches!(
+            load_program("/nonexistent/intcode.txt"),
+            Err(LoadError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+use super::disasm::Instruction;
+use super::word::Word;
+use std::collections::HashMap;
+
+pub const PAGE_SIZE: usize = 4096;
+
+// The memory limit new machines start with. It is far more than any puzzle needs, but a
+// stray write to a huge address fails with `MemoryLimitExceeded` instead of exhausting the
+// host. `set_memory_limit(None)` lifts it.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+// How an `IntCodeMachine` stores its memory. `Dense` is a single vector that grows to the
+// highest address written; `Paged` only allocates the pages that are actually touched, so
+// programs that scatter writes across huge addresses stay cheap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryBackend {
+    Dense,
+    Paged,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Memory<W: Word = i64> {
+    Dense(Vec<W>),
+    Paged {
+        pages: HashMap<usize, Box<[W]>>,
+        len: usize,
+    },
+}
+
+impl<W: Word> Memory<W> {
+    pub(crate) fn new(backend: MemoryBackend, program: &[W]) -> Memory<W> {
+        match backend {
+            MemoryBackend::Dense => Memory::Dense(program.to_vec()),
+            MemoryBackend::Paged => {
+                let mut memory = Memory::Paged {
+                    pages: HashMap::new(),
+                    len: 0,
+                };
+                for (index, value) in program.iter().enumerate() {
+                    memory.set(index, value.clone());
+                }
+                memory
+            }
+        }
+    }
+
+    pub(crate) fn backend(&self) -> MemoryBackend {
+        match self {
+            Memory::Dense(_) => MemoryBackend::Dense,
+            Memory::Paged { .. } => MemoryBackend::Paged,
+        }
+    }
+
+    // One past the highest address that has been loaded or written.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Memory::Dense(cells) => cells.len(),
+            Memory::Paged { len, .. } => *len,
+        }
+    }
+
+    // The length of the memory that is allocated without gaps from address 0.
+    pub(crate) fn contiguous_len(&self) -> usize {
+        match self {
+            Memory::Dense(cells) => cells.len(),
+            Memory::Paged { pages, len } => {
+                let count = (0..).take_while(|n| pages.contains_key(n)).count();
+                (count * PAGE_SIZE).min(*len)
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> W {
+        match self {
+            Memory::Dense(cells) => match cells.get(index) {
+                Some(v) => v.clone(),
+                None => W::zero(),
+            },
+            Memory::Paged { pages, .. } => match pages.get(&(index / PAGE_SIZE)) {
+                Some(page) => page[index % PAGE_SIZE].clone(),
+                None => W::zero(),
+            },
+        }
+    }
+
+    // The number of cells that would be allocated after writing to `index`, or `None` if
+    // `index` is the highest `usize` and cannot be written at all.
+    pub(crate) fn allocated_after_write(&self, index: usize) -> Option<usize> {
+        let end = index.checked_add(1)?;
+        match self {
+            Memory::Dense(cells) => Some(cells.len().max(end)),
+            Memory::Paged { pages, .. } => match pages.contains_key(&(index / PAGE_SIZE)) {
+                true => Some(pages.len() * PAGE_SIZE),
+                false => Some((pages.len() + 1) * PAGE_SIZE),
+            },
+        }
+    }
+
+    // Callers check `allocated_after_write` first, which rules out the highest `usize`.
+    pub(crate) fn set(&mut self, index: usize, value: W) {
+        let end = index.checked_add(1).expect("memory address out of range");
+        match self {
+            Memory::Dense(cells) => {
+                if index >= cells.len() {
+                    cells.resize(end, W::zero());
+                }
+                cells[index] = value;
+            }
+            Memory::Paged { pages, len } => {
+                let page = pages
+                    .entry(index / PAGE_SIZE)
+                    .or_insert_with(|| vec![W::zero(); PAGE_SIZE].into_boxed_slice());
+                page[index % PAGE_SIZE] = value;
+                *len = (*len).max(end);
+            }
+        }
+    }
+
+    // Every non-zero cell in address order. Unwritten memory reads as zero, so this is
+    // the whole observable content regardless of backend.
+    pub(crate) fn cells(&self) -> Vec<(usize, W)> {
+        match self {
+            Memory::Dense(cells) => cells
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !v.is_zero())
+                .map(|(i, v)| (i, v.clone()))
+                .collect(),
+            Memory::Paged { pages, .. } => {
+                let mut numbers: Vec<&usize> = pages.keys().collect();
+                numbers.sort();
+                numbers
+                    .into_iter()
+                    .flat_map(|number| {
+                        pages[number]
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, v)| !v.is_zero())
+                            .map(move |(i, v)| (number * PAGE_SIZE + i, v.clone()))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    pub(crate) fn read_range(&self, start: usize, length: usize) -> Vec<W> {
+        (start..start.saturating_add(length))
+            .map(|a| self.get(a))
+            .collect()
+    }
+}
+
+impl Memory {
+    pub(crate) fn decode(&self, address: usize) -> Option<Instruction> {
+        let window = self.read_range(address, 4);
+        let mut instruction = Instruction::decode(&window, 0)?;
+        instruction.address = address;
+        Some(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryBackend, DEFAULT_MEMORY_LIMIT, PAGE_SIZE};
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    #[test]
+    fn test_paged_memory_handles_huge_addresses() {
+        // Stores 7 at address 10^12, then doubles it in place and prints it.
+        let program = vec![
+            1101,
+            0,
+            7,
+            1000000000000,
+            1002,
+            1000000000000,
+            2,
+            1000000000000,
+            4,
+            1000000000000,
+            99,
+        ];
+
+        let mut machine = IntCodeMachine::with_memory(&program, MemoryBackend::Paged);
+        assert_eq!(machine.memory_backend(), MemoryBackend::Paged);
+        assert_eq!(machine.run(), Ok(RunState::Output(14)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert_eq!(
+            machine.registers.allocated_after_write(0),
+            Some(2 * PAGE_SIZE)
+        );
+        assert_eq!(machine.registers.allocated_after_write(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+
+        let mut dense = IntCodeMachine::with_memory(&quine, MemoryBackend::Dense);
+        let mut paged = IntCodeMachine::with_memory(&quine, MemoryBackend::Paged);
+        for _ in 0..5 {
+            assert_eq!(dense.run(), paged.run());
+        }
+        assert_eq!(dense, paged);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let program = vec![1101, 0, 7, 1000000000000, 99];
+
+        let mut dense = IntCodeMachine::with_memory(&program, MemoryBackend::Dense);
+        dense.set_memory_limit(Some(1 << 20));
+        assert_eq!(
+            dense.run(),
+            Err(IntCodeError::MemoryLimitExceeded {
+                address: 0,
+                target: 1000000000000,
+                limit: 1 << 20,
+            })
+        );
+
+        let mut paged = IntCodeMachine::with_memory(&program, MemoryBackend::Paged);
+        paged.set_memory_limit(Some(1 << 20));
+        assert_eq!(paged.run(), Ok(RunState::Halted));
+
+        let mut paged = IntCodeMachine::with_memory(&program, MemoryBackend::Paged);
+        paged.set_memory_limit(Some(PAGE_SIZE));
+        assert!(paged.run().is_err());
+
+        let mut machine = IntCodeMachine::new(&[1101, 0, 7, 100, 4, 100, 99]);
+        machine.set_memory_limit(Some(101));
+        assert_eq!(machine.run(), Ok(RunState::Output(7)));
+
+        // New machines are limited too, so a stray write cannot exhaust the host.
+        let mut dense = IntCodeMachine::new(&program);
+        assert_eq!(
+            dense.run(),
+            Err(IntCodeError::MemoryLimitExceeded {
+                address: 0,
+                target: 1000000000000,
+                limit: DEFAULT_MEMORY_LIMIT,
+            })
+        );
+    }
+}
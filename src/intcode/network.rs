@@ -0,0 +1,368 @@
+// A network of machines that talk in (destination, x, y) packets. Every machine boots with
+// its address as its first input and reads -1 whenever no packet is waiting for it.
+//
+// Machines take turns in address order, each running until it asks for input again, so a
+// given program always produces the same packets in the same order. Packets sent to the NAT
+// address are held there; once the whole network is idle, the NAT sends the last one it
+// received to address 0.
+
+use super::{IntCodeError, IntCodeMachine, RunState};
+use std::collections::VecDeque;
+use std::fmt;
+
+pub const NAT_ADDRESS: i64 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Packet {
+    pub source: i64,
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkEvent {
+    NatReceived(Packet),
+    NatSent(Packet),
+    // The NAT sent address 0 the same y value twice in a row.
+    Duplicate(Packet),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    Machine { address: usize, error: IntCodeError },
+    UnknownDestination(Packet),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Machine { address, error } => {
+                write!(f, "machine {} failed: {}", address, error)
+            }
+            NetworkError::UnknownDestination(packet) => write!(
+                f,
+                "machine {} sent a packet to unknown address {}",
+                packet.source, packet.destination
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+type Tracer = Box<dyn FnMut(u64, &Packet) + Send>;
+
+struct Node {
+    machine: IntCodeMachine,
+    queue: VecDeque<(i64, i64)>,
+    partial: Vec<i64>,
+    idle_reads: usize,
+    halted: bool,
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    nat_address: Option<i64>,
+    idle_threshold: usize,
+    nat_packet: Option<Packet>,
+    last_nat_y: Option<i64>,
+    ticks: u64,
+    tracer: Option<Tracer>,
+}
+
+impl Network {
+    pub fn new(program: &[i64], size: usize) -> Network {
+        let nodes = (0..size)
+            .map(|address| {
+                let mut machine = IntCodeMachine::new(program);
+                machine.provide_input(address as i64);
+                Node {
+                    machine,
+                    queue: VecDeque::new(),
+                    partial: vec![],
+                    idle_reads: 0,
+                    halted: false,
+                }
+            })
+            .collect();
+
+        Network {
+            nodes,
+            nat_address: Some(NAT_ADDRESS),
+            idle_threshold: 2,
+            nat_packet: None,
+            last_nat_y: None,
+            ticks: 0,
+            tracer: None,
+        }
+    }
+
+    // `None` removes the NAT, making packets to any address outside the network an error.
+    pub fn set_nat_address(&mut self, address: Option<i64>) {
+        self.nat_address = address;
+    }
+
+    // How many times in a row every machine must read -1 before the network counts as idle.
+    pub fn set_idle_threshold(&mut self, reads: usize) {
+        self.idle_threshold = reads.max(1);
+    }
+
+    // Called with the current tick for every packet routed, including those sent by the NAT.
+    pub fn set_tracer<F: FnMut(u64, &Packet) + Send + 'static>(&mut self, tracer: F) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn machine(&self, address: usize) -> Option<&IntCodeMachine> {
+        self.nodes.get(address).map(|node| &node.machine)
+    }
+
+    // Gives every machine one turn, then lets the NAT act if the network is idle.
+    pub fn tick(&mut self) -> Result<Vec<NetworkEvent>, NetworkError> {
+        self.ticks += 1;
+        let mut events = vec![];
+
+        for address in 0..self.nodes.len() {
+            let node = &mut self.nodes[address];
+            if node.halted {
+                continue;
+            }
+
+            if node.machine.is_awaiting_input() {
+                match node.queue.pop_front() {
+                    Some((x, y)) => {
+                        node.machine.provide_input(x);
+                        node.machine.provide_input(y);
+                        node.idle_reads = 0;
+                    }
+                    None => {
+                        node.machine.provide_input(-1);
+                        node.idle_reads += 1;
+                    }
+                }
+            }
+
+            loop {
+                let node = &mut self.nodes[address];
+                match node.machine.run() {
+                    Ok(RunState::Output(v)) => {
+                        node.idle_reads = 0;
+                        node.partial.push(v);
+                        if node.partial.len() == 3 {
+                            let packet = Packet {
+                                source: address as i64,
+                                destination: node.partial[0],
+                                x: node.partial[1],
+                                y: node.partial[2],
+                            };
+                            node.partial.clear();
+                            if let Some(event) = self.route(packet)? {
+                                events.push(event);
+                            }
+                        }
+                    }
+                    Ok(RunState::AwaitingInput) => break,
+                    Ok(RunState::Halted) => {
+                        node.halted = true;
+                        break;
+                    }
+                    Err(error) => return Err(NetworkError::Machine { address, error }),
+                }
+            }
+        }
+
+        if self.is_idle() {
+            if let (Some(nat), Some(packet)) = (self.nat_address, self.nat_packet) {
+                let packet = Packet {
+                    source: nat,
+                    destination: 0,
+                    ..packet
+                };
+                self.route(packet)?;
+                events.push(NetworkEvent::NatSent(packet));
+                if self.last_nat_y == Some(packet.y) {
+                    events.push(NetworkEvent::Duplicate(packet));
+                }
+                self.last_nat_y = Some(packet.y);
+                for node in self.nodes.iter_mut() {
+                    node.idle_reads = 0;
+                }
+            }
+        }
+
+        Ok(events)
+    }
+
+    // Ticks until `stop` accepts an event, which is returned, or until `max_ticks` ticks or
+    // every machine halting end the run with `None`.
+    pub fn run_until<F: FnMut(&NetworkEvent) -> bool>(
+        &mut self,
+        max_ticks: u64,
+        mut stop: F,
+    ) -> Result<Option<NetworkEvent>, NetworkError> {
+        for _ in 0..max_ticks {
+            if self.nodes.iter().all(|node| node.halted) {
+                break;
+            }
+
+            for event in self.tick()? {
+                if stop(&event) {
+                    return Ok(Some(event));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn is_idle(&self) -> bool {
+        self.nodes.iter().all(|node| {
+            node.halted || (node.queue.is_empty() && node.idle_reads >= self.idle_threshold)
+        })
+    }
+
+    fn route(&mut self, packet: Packet) -> Result<Option<NetworkEvent>, NetworkError> {
+        let ticks = self.ticks;
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer(ticks, &packet);
+        }
+
+        if Some(packet.destination) == self.nat_address {
+            self.nat_packet = Some(packet);
+            return Ok(Some(NetworkEvent::NatReceived(packet)));
+        }
+
+        if packet.destination < 0 || packet.destination as usize >= self.nodes.len() {
+            return Err(NetworkError::UnknownDestination(packet));
+        }
+
+        self.nodes[packet.destination as usize]
+            .queue
+            .push_back((packet.x, packet.y));
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Network, NetworkError, NetworkEvent, Packet};
+    use crate::intcode::asm::assemble;
+    use std::sync::{Arc, Mutex};
+
+    // Machine 0 starts a packet down the line 0 -> 1 -> 2, each hop adding 1 to y. Machine 2
+    // sends the NAT (x + y, 5), so the NAT's second wake-up repeats its first y.
+    fn relay() -> Vec<i64> {
+        assemble(
+            "
+                    in  addr
+                    eq  addr, #0, flag
+                    jf  flag, #loop
+                    out #1
+                    out #7
+                    out #0
+            loop:   in  x
+                    eq  x, #-1, flag
+                    jt  flag, #loop
+                    in  y
+                    eq  addr, #2, flag
+                    jt  flag, #last
+                    add addr, #1, dest
+                    out dest
+                    out x
+                    add y, #1, y
+                    out y
+                    jt  #1, #loop
+            last:   out #255
+                    add x, y, x
+                    out x
+                    out #5
+                    jt  #1, #loop
+            addr:   .data 0
+            flag:   .data 0
+            dest:   .data 0
+            x:      .data 0
+            y:      .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nat_resends_and_reports_duplicates() {
+        let routed = Arc::new(Mutex::new(vec![]));
+        let recorder = routed.clone();
+
+        let mut network = Network::new(&relay(), 3);
+        network.set_tracer(move |_, packet: &Packet| recorder.lock().unwrap().push(*packet));
+
+        let first = network
+            .run_until(100, |e| matches!(e, NetworkEvent::NatReceived(_)))
+            .unwrap();
+        assert_eq!(
+            first,
+            Some(NetworkEvent::NatReceived(Packet {
+                source: 2,
+                destination: 255,
+                x: 8,
+                y: 5
+            }))
+        );
+
+        let duplicate = network
+            .run_until(100, |e| matches!(e, NetworkEvent::Duplicate(_)))
+            .unwrap();
+        assert_eq!(
+            duplicate,
+            Some(NetworkEvent::Duplicate(Packet {
+                source: 255,
+                destination: 0,
+                x: 15,
+                y: 5
+            }))
+        );
+
+        let routed: Vec<(i64, i64, i64)> = routed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|p| (p.source, p.destination, p.y))
+            .collect();
+        assert_eq!(
+            routed,
+            vec![
+                (0, 1, 0),
+                (1, 2, 1),
+                (2, 255, 5),
+                (255, 0, 5),
+                (0, 1, 6),
+                (1, 2, 7),
+                (2, 255, 5),
+                (255, 0, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_network_without_nat() {
+        let mut network = Network::new(&relay(), 3);
+        network.set_nat_address(None);
+        assert_eq!(
+            network.run_until(100, |_| true),
+            Err(NetworkError::UnknownDestination(Packet {
+                source: 2,
+                destination: 255,
+                x: 8,
+                y: 5
+            }))
+        );
+
+        let mut network = Network::new(&[3, 0, 99], 2);
+        assert_eq!(network.run_until(100, |_| true), Ok(None));
+        assert_eq!(network.ticks(), 1);
+        assert!(network.machine(1).unwrap().is_halted());
+    }
+}
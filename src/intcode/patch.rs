@@ -0,0 +1,221 @@
+// A set of memory patches, such as day 2's noun and verb or day 13's free play, that can be
+// applied to a program before it is loaded or to a machine while it runs. Patch sets print
+// and parse as `address=value` pairs separated by commas, e.g. `1=12,2=2`, so a run can be
+// reproduced from its log.
+
+use super::memory::DEFAULT_MEMORY_LIMIT;
+use super::word::Word;
+use super::{IntCodeError, IntCodeMachine};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PatchSet {
+    cells: BTreeMap<usize, i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParsePatchError {
+    pub entry: String,
+}
+
+impl fmt::Display for ParsePatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected <address>=<value>, got '{}'", self.entry)
+    }
+}
+
+impl std::error::Error for ParsePatchError {}
+
+impl PatchSet {
+    pub fn new() -> PatchSet {
+        PatchSet::default()
+    }
+
+    // A later patch to the same address replaces the earlier one.
+    pub fn set(&mut self, address: usize, value: i64) -> &mut PatchSet {
+        self.cells.insert(address, value);
+        self
+    }
+
+    // Values that would land past the highest address are dropped, as no machine could
+    // store them.
+    pub fn set_range(&mut self, start: usize, values: &[i64]) -> &mut PatchSet {
+        for (offset, value) in values.iter().enumerate() {
+            if let Some(address) = start.checked_add(offset) {
+                self.cells.insert(address, *value);
+            }
+        }
+        self
+    }
+
+    pub fn extend(&mut self, other: &PatchSet) -> &mut PatchSet {
+        self.cells.extend(other.cells.iter());
+        self
+    }
+
+    pub fn get(&self, address: usize) -> Option<i64> {
+        self.cells.get(&address).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        self.cells.iter().map(|(a, v)| (*a, *v))
+    }
+
+    // Patches past the end of the program grow it with zeros, as the machine would, up to
+    // `DEFAULT_MEMORY_LIMIT` cells. Use `apply_to` to patch huge addresses in a paged
+    // machine.
+    pub fn apply(&self, program: &mut Vec<i64>) -> Result<(), IntCodeError> {
+        for (address, value) in self.iter() {
+            if address >= program.len() {
+                match address.checked_add(1) {
+                    Some(length) if length <= DEFAULT_MEMORY_LIMIT => program.resize(length, 0),
+                    _ => {
+                        return Err(IntCodeError::MemoryLimitExceeded {
+                            address: 0,
+                            target: address,
+                            limit: DEFAULT_MEMORY_LIMIT,
+                        })
+                    }
+                }
+            }
+            program[address] = value;
+        }
+        Ok(())
+    }
+
+    pub fn patched(&self, program: &[i64]) -> Result<Vec<i64>, IntCodeError> {
+        let mut program = program.to_vec();
+        self.apply(&mut program)?;
+        Ok(program)
+    }
+
+    pub fn apply_to<W: Word>(&self, machine: &mut IntCodeMachine<W>) -> Result<(), IntCodeError> {
+        for (address, value) in self.iter() {
+            machine.poke(address, W::from_i64(value))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PatchSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries: Vec<String> = self
+            .iter()
+            .map(|(address, value)| format!("{}={}", address, value))
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+impl FromStr for PatchSet {
+    type Err = ParsePatchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut patches = PatchSet::new();
+        for entry in s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|e| !e.is_empty())
+        {
+            let error = || ParsePatchError {
+                entry: entry.to_owned(),
+            };
+            let mut parts = entry.splitn(2, '=');
+            let address = parts.next().and_then(|a| a.parse::<usize>().ok());
+            let value = parts.next().and_then(|v| v.parse::<i64>().ok());
+            match (address, value) {
+                (Some(address), Some(value)) => patches.set(address, value),
+                _ => return Err(error()),
+            };
+        }
+        Ok(patches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParsePatchError, PatchSet};
+    use crate::intcode::memory::MemoryBackend;
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    #[test]
+    fn test_print_and_parse() {
+        let mut patches = PatchSet::new();
+        patches.set(2, 2).set(1, 12).set_range(10, &[-1, 0]);
+        assert_eq!(patches.to_string(), "1=12,2=2,10=-1,11=0");
+        assert_eq!(patches.to_string().parse::<PatchSet>(), Ok(patches));
+        assert_eq!("".parse::<PatchSet>(), Ok(PatchSet::new()));
+        assert_eq!(" 0=1, 0=2 ".parse::<PatchSet>().unwrap().get(0), Some(2));
+
+        assert_eq!(
+            "1=12,2".parse::<PatchSet>(),
+            Err(ParsePatchError {
+                entry: String::from("2")
+            })
+        );
+        assert!("-1=3".parse::<PatchSet>().is_err());
+        assert!("1=x".parse::<PatchSet>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        // Adds the values at 9 and 10 and prints the sum.
+        let program = vec![1, 9, 10, 11, 4, 11, 99, 0, 0, 0];
+        let patches: PatchSet = "9=30,10=12".parse().unwrap();
+        assert_eq!(patches.patched(&program).unwrap().len(), 11);
+
+        let mut machine = IntCodeMachine::new(&patches.patched(&program).unwrap());
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+
+        // Patching a running machine takes effect at the next instruction.
+        let mut machine = IntCodeMachine::new(&program);
+        patches.apply_to(&mut machine).unwrap();
+        assert_eq!(machine.peek_range(9, 3), vec![30, 12, 0]);
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+
+        machine.set_memory_limit(Some(16));
+        assert_eq!(
+            machine.poke(100, 1),
+            Err(IntCodeError::MemoryLimitExceeded {
+                address: 6,
+                target: 100,
+                limit: 16
+            })
+        );
+    }
+
+    #[test]
+    fn test_huge_addresses() {
+        let mut patches = PatchSet::new();
+        patches.set(1000000000000, 1).set_range(usize::MAX, &[2, 3]);
+        assert_eq!(patches.len(), 2);
+        assert!(patches.patched(&[99]).is_err());
+
+        let mut machine = IntCodeMachine::with_memory(&[99], MemoryBackend::Paged);
+        assert!(patches.apply_to(&mut machine).is_err());
+        patches = "1000000000000=1".parse().unwrap();
+        patches.apply_to(&mut machine).unwrap();
+        assert_eq!(machine.peek(1000000000000), 1);
+        assert!(machine.poke_range(usize::MAX, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_poke_invalidates_decoded_code() {
+        // Prints 7 until the output instruction at 0 is patched into a halt.
+        let program = vec![104, 7, 1105, 1, 0];
+        let mut machine = IntCodeMachine::new(&program);
+        assert_eq!(machine.run(), Ok(RunState::Output(7)));
+        machine.poke_range(0, &[99]).unwrap();
+        assert_eq!(machine.peek(0), 99);
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+}
@@ -0,0 +1,294 @@
+// Runs a group of machines on their own threads, with the outputs of each machine sent over
+// channels to the inputs of the machines it is connected to. Seed a machine's first inputs
+// (e.g. a phase setting) with `provide_input` before adding it.
+//
+// The run ends once every machine has halted, faulted or is blocked on input that nothing
+// can provide any more.
+
+use super::io::InputSource;
+use super::{IntCodeError, IntCodeMachine, RunState};
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MachineStatus {
+    Halted,
+    Blocked,
+    Faulted(IntCodeError),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PipelineResult {
+    // Everything each machine output, in order, whether or not it was connected.
+    pub outputs: Vec<Vec<i64>>,
+    pub status: Vec<MachineStatus>,
+}
+
+impl PipelineResult {
+    pub fn deadlocked(&self) -> bool {
+        self.status.contains(&MachineStatus::Blocked)
+    }
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    machines: Vec<IntCodeMachine>,
+    links: Vec<(usize, usize)>,
+}
+
+// `None` on a channel wakes a blocked machine once the pipeline has stopped.
+struct State {
+    senders: Vec<Sender<Option<i64>>>,
+    pending: Vec<usize>,
+    waiting: Vec<bool>,
+    finished: Vec<bool>,
+    stopped: bool,
+}
+
+impl State {
+    fn send(&mut self, to: usize, value: i64) {
+        self.pending[to] += 1;
+        let _ = self.senders[to].send(Some(value));
+    }
+
+    fn check_stopped(&mut self) {
+        if self.stopped {
+            return;
+        }
+
+        let stuck = |i: usize| self.finished[i] || (self.waiting[i] && self.pending[i] == 0);
+        if (0..self.senders.len()).all(stuck) {
+            self.stopped = true;
+            for (i, sender) in self.senders.iter().enumerate() {
+                if !self.finished[i] {
+                    let _ = sender.send(None);
+                }
+            }
+        }
+    }
+}
+
+struct PipeInput {
+    index: usize,
+    receiver: Receiver<Option<i64>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl InputSource for PipeInput {
+    fn read(&mut self) -> io::Result<Option<i64>> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Ok(message) = self.receiver.try_recv() {
+                if message.is_some() {
+                    state.pending[self.index] -= 1;
+                }
+                return Ok(message);
+            }
+
+            state.waiting[self.index] = true;
+            state.check_stopped();
+        }
+
+        let message = self.receiver.recv();
+        let mut state = self.state.lock().unwrap();
+        state.waiting[self.index] = false;
+        match message {
+            Ok(Some(v)) => {
+                state.pending[self.index] -= 1;
+                Ok(Some(v))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    // Each machine feeds the next one.
+    pub fn series(machines: Vec<IntCodeMachine>) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for machine in machines {
+            let index = pipeline.add(machine);
+            if index > 0 {
+                pipeline.connect(index - 1, index);
+            }
+        }
+
+        pipeline
+    }
+
+    // A series whose last machine feeds back into the first.
+    pub fn feedback_loop(machines: Vec<IntCodeMachine>) -> Pipeline {
+        let mut pipeline = Pipeline::series(machines);
+        if !pipeline.machines.is_empty() {
+            pipeline.connect(pipeline.machines.len() - 1, 0);
+        }
+
+        pipeline
+    }
+
+    pub fn add(&mut self, machine: IntCodeMachine) -> usize {
+        self.machines.push(machine);
+        self.machines.len() - 1
+    }
+
+    // Sends every output of `from` to the input of `to`. A machine connected to several
+    // others sends each of them every output.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        assert!(
+            from < self.machines.len() && to < self.machines.len(),
+            "no machine {} in a pipeline of {}",
+            from.max(to),
+            self.machines.len()
+        );
+        self.links.push((from, to));
+    }
+
+    pub fn run(self) -> PipelineResult {
+        let Pipeline { machines, links } = self;
+        let count = machines.len();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| channel()).unzip();
+        let state = Arc::new(Mutex::new(State {
+            senders,
+            pending: vec![0; count],
+            waiting: vec![false; count],
+            finished: vec![false; count],
+            stopped: false,
+        }));
+
+        let workers: Vec<_> = machines
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(index, (mut machine, receiver))| {
+                let targets: Vec<usize> = links
+                    .iter()
+                    .filter(|(from, _)| *from == index)
+                    .map(|(_, to)| *to)
+                    .collect();
+                let state = state.clone();
+                machine.clear_output_sink();
+                machine.set_input_source(PipeInput {
+                    index,
+                    receiver,
+                    state: state.clone(),
+                });
+
+                thread::spawn(move || {
+                    let mut outputs = vec![];
+                    let status = loop {
+                        match machine.run() {
+                            Ok(RunState::Output(v)) => {
+                                outputs.push(v);
+                                let mut state = state.lock().unwrap();
+                                for to in &targets {
+                                    state.send(*to, v);
+                                }
+                            }
+                            Ok(RunState::AwaitingInput) => break MachineStatus::Blocked,
+                            Ok(RunState::Halted) => break MachineStatus::Halted,
+                            Err(e) => break MachineStatus::Faulted(e),
+                        }
+                    };
+
+                    let mut state = state.lock().unwrap();
+                    state.finished[index] = true;
+                    state.check_stopped();
+                    (outputs, status)
+                })
+            })
+            .collect();
+
+        let (outputs, status) = workers
+            .into_iter()
+            .map(|w| w.join().expect("intcode machine thread panicked"))
+            .unzip();
+
+        PipelineResult { outputs, status }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MachineStatus, Pipeline};
+    use crate::intcode::{IntCodeError, IntCodeMachine};
+
+    fn amplifiers(program: &[i64], phases: &[i64]) -> Vec<IntCodeMachine> {
+        phases
+            .iter()
+            .map(|phase| {
+                let mut machine = IntCodeMachine::new(program);
+                machine.provide_input(*phase);
+                machine
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_series_and_feedback_loop() {
+        let program = vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let mut machines = amplifiers(&program, &[4, 3, 2, 1, 0]);
+        machines[0].provide_input(0);
+        let result = Pipeline::series(machines).run();
+        assert_eq!(result.outputs[4], vec![43210]);
+        assert_eq!(result.status, vec![MachineStatus::Halted; 5]);
+        assert!(!result.deadlocked());
+
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut machines = amplifiers(&program, &[9, 8, 7, 6, 5]);
+        machines[0].provide_input(0);
+        let result = Pipeline::feedback_loop(machines).run();
+        assert_eq!(result.outputs[4].last(), Some(&139629729));
+        assert_eq!(result.status, vec![MachineStatus::Halted; 5]);
+    }
+
+    #[test]
+    fn test_deadlock_and_faults() {
+        // `echo` repeats its input until it sees a 0, then reads one last value and halts. The
+        // second machine counts down whatever it is sent, so it is left waiting at the end.
+        let echo = vec![3, 20, 4, 20, 1005, 20, 0, 3, 20, 99];
+
+        let mut pipeline = Pipeline::new();
+        let mut first = IntCodeMachine::new(&echo);
+        first.provide_input(3);
+        let a = pipeline.add(first);
+        let b = pipeline.add(IntCodeMachine::new(&[
+            3, 11, 1001, 11, -1, 11, 4, 11, 1105, 1, 0,
+        ]));
+        pipeline.connect(a, b);
+        pipeline.connect(b, a);
+        let result = pipeline.run();
+        assert_eq!(result.outputs, vec![vec![3, 2, 1, 0], vec![2, 1, 0, -1]]);
+        assert_eq!(
+            result.status,
+            vec![MachineStatus::Halted, MachineStatus::Blocked]
+        );
+        assert!(result.deadlocked());
+
+        let mut pipeline = Pipeline::new();
+        let a = pipeline.add(IntCodeMachine::new(&[104, 1, 42]));
+        let b = pipeline.add(IntCodeMachine::new(&[3, 0, 3, 0, 99]));
+        pipeline.connect(a, b);
+        let result = pipeline.run();
+        assert_eq!(
+            result.status,
+            vec![
+                MachineStatus::Faulted(IntCodeError::InvalidOpcode {
+                    address: 2,
+                    value: 42
+                }),
+                MachineStatus::Blocked
+            ]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use super::disasm::{Entry, Listing};
+use super::word::Word;
+use super::{IntCodeError, OpCode, ParameterMode};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub instructions: u64,
+    pub outputs: u64,
+    pub input_waits: u64,
+    pub by_address: HashMap<usize, u64>,
+    pub by_opcode: HashMap<OpCode, u64>,
+    pub by_modes: HashMap<(OpCode, Vec<ParameterMode>), u64>,
+    // Taken jumps keyed by (from, to); backward ones close a loop.
+    pub jumps: HashMap<(usize, usize), u64>,
+}
+
+fn sorted<K: Clone + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut entries: Vec<(K, u64)> = counts.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    entries
+}
+
+fn describe_modes(opcode: OpCode, modes: &[ParameterMode]) -> String {
+    let modes: Vec<&str> = modes
+        .iter()
+        .map(|m| match m {
+            ParameterMode::Position => "pos",
+            ParameterMode::Immediate => "imm",
+            ParameterMode::Relative => "rel",
+        })
+        .collect();
+
+    format!("{} {}", opcode.mnemonic(), modes.join(","))
+}
+
+impl Profile {
+    pub(crate) fn record<W: Word>(
+        &mut self,
+        address: usize,
+        instruction: i64,
+        result: &Result<Option<W>, IntCodeError>,
+        next: usize,
+    ) {
+        match result {
+            Ok(output) => {
+                if output.is_some() {
+                    self.outputs += 1;
+                }
+            }
+            Err(IntCodeError::NeedInput) => {
+                self.input_waits += 1;
+                return;
+            }
+            Err(_) => return,
+        }
+
+        // `execute` has already validated the instruction, so decoding cannot fail here.
+        let opcode = OpCode::from_instruction(instruction);
+        let modes: Vec<ParameterMode> = (1..=opcode.parameter_count() as i64)
+            .map(|n| ParameterMode::from_instruction_and_number(instruction, n))
+            .collect();
+
+        self.instructions += 1;
+        *self.by_address.entry(address).or_insert(0) += 1;
+        *self.by_opcode.entry(opcode).or_insert(0) += 1;
+
+        let fallthrough = address + 1 + opcode.parameter_count();
+        if next != fallthrough {
+            *self.jumps.entry((address, next)).or_insert(0) += 1;
+        }
+
+        *self.by_modes.entry((opcode, modes)).or_insert(0) += 1;
+    }
+
+    // A hot-spot report of the `top` busiest addresses, opcodes, mode combinations and loops.
+    // Passing the program's disassembly shows the instruction at each hot address.
+    pub fn report(&self, listing: Option<&Listing>, top: usize) -> String {
+        let mut report = String::new();
+        let total = self.instructions.max(1) as f64;
+        let percent = |count: u64| 100.0 * count as f64 / total;
+        let describe = |address: usize| match listing.and_then(|l| l.entry_at(address)) {
+            Some(Entry::Code(instruction)) if instruction.address == address => {
+                instruction.to_string()
+            }
+            Some(Entry::Code(instruction)) => format!("(inside {})", instruction),
+            Some(Entry::Data { .. }) => String::from("(data)"),
+            None => String::new(),
+        };
+
+        let _ = writeln!(report, "instructions executed: {}", self.instructions);
+        let _ = writeln!(report, "outputs:               {}", self.outputs);
+        let _ = writeln!(report, "input waits:           {}", self.input_waits);
+
+        let _ = writeln!(report, "\nhot spots:");
+        for (address, count) in sorted(&self.by_address).into_iter().take(top) {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>8}  {}",
+                count,
+                percent(count),
+                address,
+                describe(address)
+            );
+        }
+
+        let _ = writeln!(report, "\nby opcode:");
+        let mut opcodes: Vec<(OpCode, u64)> =
+            self.by_opcode.iter().map(|(k, v)| (*k, *v)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.code().cmp(&b.0.code())));
+        for (opcode, count) in opcodes.into_iter().take(top) {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}%  {}",
+                count,
+                percent(count),
+                opcode.mnemonic()
+            );
+        }
+
+        let _ = writeln!(report, "\nby parameter modes:");
+        let mut modes: Vec<(String, u64)> = self
+            .by_modes
+            .iter()
+            .map(|((opcode, modes), count)| (describe_modes(*opcode, modes), *count))
+            .collect();
+        modes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (description, count) in modes.into_iter().take(top) {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}%  {}",
+                count,
+                percent(count),
+                description
+            );
+        }
+
+        let _ = writeln!(report, "\nhot loops:");
+        let loops = sorted(&self.jumps)
+            .into_iter()
+            .filter(|((from, to), _)| to <= from)
+            .take(top);
+        for ((from, to), count) in loops {
+            let body: u64 = self
+                .by_address
+                .iter()
+                .filter(|(address, _)| (to..=from).contains(address))
+                .map(|(_, count)| *count)
+                .sum();
+            let _ = writeln!(
+                report,
+                "{:>12} iterations  {:>6} -> {:<6} {:>6.2}% of instructions  {}",
+                count,
+                from,
+                to,
+                percent(body),
+                describe(to)
+            );
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::disasm::disassemble;
+    use crate::intcode::{IntCodeMachine, OpCode, ParameterMode, RunState};
+
+    fn quine() -> Vec<i64> {
+        vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]
+    }
+
+    #[test]
+    fn test_profile_counts() {
+        let mut machine = IntCodeMachine::new(&quine());
+        machine.enable_profiling();
+        while machine.run() != Ok(RunState::Halted) {}
+
+        let profile = machine.take_profile().unwrap();
+        assert_eq!(profile.instructions, 16 * 5);
+        assert_eq!(profile.outputs, 16);
+        assert_eq!(profile.input_waits, 0);
+        assert_eq!(profile.by_address[&0], 16);
+        assert_eq!(profile.by_address.get(&15), None);
+        assert_eq!(profile.by_opcode[&OpCode::JumpIfFalse], 16);
+        assert_eq!(
+            profile.by_modes[&(
+                OpCode::Add,
+                vec![
+                    ParameterMode::Position,
+                    ParameterMode::Immediate,
+                    ParameterMode::Position
+                ]
+            )],
+            16
+        );
+        assert_eq!(profile.jumps[&(12, 0)], 15);
+        assert!(machine.profile().is_none());
+    }
+
+    #[test]
+    fn test_input_waits_and_report() {
+        let program = vec![3, 7, 4, 7, 1105, 1, 0, 0];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.enable_profiling();
+
+        for v in 0..3 {
+            assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+            machine.provide_input(v);
+            assert_eq!(machine.run(), Ok(RunState::Output(v)));
+        }
+
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.input_waits, 3);
+        assert_eq!(profile.outputs, 3);
+
+        let report = profile.report(Some(&disassemble(&program)), 5);
+        assert!(report.contains("input waits:           3"));
+        assert!(report.contains("in 7"));
+        assert!(report.contains("4 -> 0"));
+        assert!(report.contains("jt imm,imm"));
+    }
+}
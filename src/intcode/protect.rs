@@ -0,0 +1,233 @@
+// Optional memory protection. Self-modifying writes are legal Intcode, but in hand-assembled
+// programs they are usually a bug, as is jumping into data. A `ProtectionMap` marks address
+// ranges read-only or non-executable; only the program's own instructions are checked, so
+// `poke` and patch sets can still change protected cells. In `ReportOnly` mode the program
+// carries on and violations are kept until taken, up to `MAX_VIOLATIONS` of them; later
+// ones are only counted.
+
+use std::fmt;
+use std::ops::Range;
+
+pub const MAX_VIOLATIONS: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Access {
+    Write,
+    Execute,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProtectionMode {
+    Enforce,
+    ReportOnly,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Violation {
+    // The instruction that made the access.
+    pub address: usize,
+    pub target: usize,
+    pub access: Access,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProtectionMap {
+    mode: ProtectionMode,
+    read_only: Vec<Range<usize>>,
+    no_execute: Vec<Range<usize>>,
+    violations: Vec<Violation>,
+    dropped: usize,
+}
+
+impl ProtectionMap {
+    pub fn new(mode: ProtectionMode) -> ProtectionMap {
+        ProtectionMap {
+            mode,
+            read_only: vec![],
+            no_execute: vec![],
+            violations: vec![],
+            dropped: 0,
+        }
+    }
+
+    // The original program image of `length` cells is read-only.
+    pub fn program_image(length: usize, mode: ProtectionMode) -> ProtectionMap {
+        let mut map = ProtectionMap::new(mode);
+        map.read_only(0..length);
+        map
+    }
+
+    pub fn mode(&self) -> ProtectionMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ProtectionMode) {
+        self.mode = mode;
+    }
+
+    pub fn read_only(&mut self, range: Range<usize>) -> &mut ProtectionMap {
+        self.read_only.push(range);
+        self
+    }
+
+    pub fn no_execute(&mut self, range: Range<usize>) -> &mut ProtectionMap {
+        self.no_execute.push(range);
+        self
+    }
+
+    pub fn is_writable(&self, address: usize) -> bool {
+        !self.read_only.iter().any(|r| r.contains(&address))
+    }
+
+    pub fn is_executable(&self, address: usize) -> bool {
+        !self.no_execute.iter().any(|r| r.contains(&address))
+    }
+
+    // Violations seen in `ReportOnly` mode, oldest first.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    // Violations that were not kept because `MAX_VIOLATIONS` were already waiting.
+    pub fn dropped_violations(&self) -> usize {
+        self.dropped
+    }
+
+    // Takes the kept violations and resets the dropped count.
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        self.dropped = 0;
+        std::mem::take(&mut self.violations)
+    }
+
+    // The violation if the access must be stopped; in `ReportOnly` mode it is logged instead.
+    pub(crate) fn check(
+        &mut self,
+        address: usize,
+        target: usize,
+        access: Access,
+    ) -> Result<(), Violation> {
+        let allowed = match access {
+            Access::Write => self.is_writable(target),
+            Access::Execute => self.is_executable(target),
+        };
+        if allowed {
+            return Ok(());
+        }
+
+        let violation = Violation {
+            address,
+            target,
+            access,
+        };
+        match self.mode {
+            ProtectionMode::Enforce => Err(violation),
+            ProtectionMode::ReportOnly => {
+                match self.violations.len() < MAX_VIOLATIONS {
+                    true => self.violations.push(violation),
+                    false => self.dropped += 1,
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Write => write!(
+                f,
+                "instruction at address {} wrote to read-only address {}",
+                self.address, self.target
+            ),
+            Access::Execute => write!(
+                f,
+                "instruction at address {} passed control to non-executable address {}",
+                self.address, self.target
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, ProtectionMap, ProtectionMode, Violation, MAX_VIOLATIONS};
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    // Writes 4 + 7 = 11 to the spare cell at 8, then prints 0.
+    const SELF_MODIFYING: [i64; 11] = [1101, 4, 7, 8, 104, 0, 99, 0, 0, 0, 0];
+
+    #[test]
+    fn test_enforced_write_protection() {
+        let mut machine = IntCodeMachine::new(&SELF_MODIFYING);
+        machine.protect_program(ProtectionMode::Enforce);
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::ProtectionViolation {
+                address: 0,
+                target: 8,
+                access: Access::Write,
+            })
+        );
+
+        // Writes outside the image and from the host are allowed.
+        let mut machine = IntCodeMachine::new(&SELF_MODIFYING);
+        let mut map = ProtectionMap::new(ProtectionMode::Enforce);
+        map.read_only(0..8);
+        machine.set_protection(Some(map));
+        machine.poke(5, 3).unwrap();
+        assert_eq!(machine.run(), Ok(RunState::Output(3)));
+        assert_eq!(machine.peek(8), 11);
+    }
+
+    #[test]
+    fn test_no_execute() {
+        // Jumps to 5, which is data.
+        let program = vec![1105, 1, 5, 99, 0, 1, 0, 0, 0];
+        let mut machine = IntCodeMachine::new(&program);
+        let mut map = ProtectionMap::new(ProtectionMode::Enforce);
+        map.no_execute(4..9);
+        machine.set_protection(Some(map));
+        let error = machine.run().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "instruction at address 0 passed control to non-executable address 5"
+        );
+    }
+
+    #[test]
+    fn test_report_only() {
+        let mut machine = IntCodeMachine::new(&[1101, 4, 7, 8, 4, 8, 99, 0, 0, 0]);
+        machine.protect_program(ProtectionMode::ReportOnly);
+        assert_eq!(machine.run(), Ok(RunState::Output(11)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+
+        let violations = machine.take_protection().unwrap().take_violations();
+        assert_eq!(
+            violations,
+            vec![Violation {
+                address: 0,
+                target: 8,
+                access: Access::Write,
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "instruction at address 0 wrote to read-only address 8"
+        );
+
+        // Writes to 7 forever; only the first `MAX_VIOLATIONS` writes are kept.
+        let mut machine = IntCodeMachine::new(&[1101, 0, 0, 7, 1105, 1, 0, 0]);
+        machine.protect_program(ProtectionMode::ReportOnly);
+        machine.set_instruction_budget(Some(2 * (MAX_VIOLATIONS as u64 + 10)));
+        assert!(matches!(
+            machine.run(),
+            Err(IntCodeError::BudgetExhausted { .. })
+        ));
+        let protection = machine.protection_mut().unwrap();
+        assert_eq!(protection.violations().len(), MAX_VIOLATIONS);
+        assert_eq!(protection.dropped_violations(), 10);
+        protection.take_violations();
+        assert_eq!(protection.dropped_violations(), 0);
+    }
+}
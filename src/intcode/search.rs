@@ -0,0 +1,303 @@
+// Searches over memory patches and inputs for runs whose outputs or final memory satisfy a
+// predicate, e.g. day 2's noun and verb. Candidates are shared between worker threads in
+// order, and `find_first` only stops workers after their current candidate, so the match it
+// returns is the earliest one in the candidate order no matter how many threads run.
+
+use super::patch::PatchSet;
+use super::{IntCodeError, IntCodeMachine, RunState};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// `Search::new` stops each run after this many instructions, so a candidate that loops
+// forever is reported as `BudgetExhausted` instead of hanging its worker.
+pub const DEFAULT_INSTRUCTION_BUDGET: u64 = 10_000_000;
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Candidate {
+    pub patches: PatchSet,
+    pub input: Vec<i64>,
+}
+
+impl Candidate {
+    pub fn new(patches: PatchSet, input: Vec<i64>) -> Candidate {
+        Candidate { patches, input }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub candidate: Candidate,
+    pub outputs: Vec<i64>,
+    // `Halted` or `AwaitingInput` once the run has stopped, or the fault that stopped it.
+    pub state: Result<RunState, IntCodeError>,
+    pub machine: IntCodeMachine,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub matches: Vec<Evaluation>,
+    pub evaluated: u64,
+}
+
+pub struct Search {
+    machine: IntCodeMachine,
+    threads: usize,
+}
+
+// A grid whose candidates cannot be counted in a `u64`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GridTooLarge {
+    pub addresses: usize,
+    pub values: std::ops::Range<i64>,
+}
+
+impl fmt::Display for GridTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a search over {} addresses with values {}..{} is too large",
+            self.addresses, self.values.start, self.values.end
+        )
+    }
+}
+
+impl std::error::Error for GridTooLarge {}
+
+// Every combination of `values` at `addresses`, varying the last address fastest.
+pub fn grid(
+    addresses: &[usize],
+    values: std::ops::Range<i64>,
+) -> Result<impl Iterator<Item = Candidate>, GridTooLarge> {
+    let too_large = || GridTooLarge {
+        addresses: addresses.len(),
+        values: values.clone(),
+    };
+    let addresses = addresses.to_vec();
+    let width = values
+        .end
+        .checked_sub(values.start)
+        .ok_or_else(too_large)?
+        .max(0) as u64;
+    let total = match addresses.len() {
+        0 => 0,
+        n => u32::try_from(n)
+            .ok()
+            .and_then(|n| width.checked_pow(n))
+            .ok_or_else(too_large)?,
+    };
+
+    Ok((0..total).map(move |mut i| {
+        let mut patches = PatchSet::new();
+        for address in addresses.iter().rev() {
+            patches.set(*address, values.start + (i % width) as i64);
+            i /= width;
+        }
+        Candidate::new(patches, vec![])
+    }))
+}
+
+// Machines are `Send` but not `Sync`, so every worker runs candidates on its own copy.
+fn evaluate(base: &IntCodeMachine, candidate: Candidate) -> Evaluation {
+    let mut machine = base.clone();
+    let mut outputs = vec![];
+    let state = match candidate.patches.apply_to(&mut machine) {
+        Ok(()) => {
+            for v in candidate.input.iter() {
+                machine.provide_input(*v);
+            }
+            loop {
+                match machine.run() {
+                    Ok(RunState::Output(v)) => outputs.push(v),
+                    state => break state,
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    Evaluation {
+        candidate,
+        outputs,
+        state,
+        machine,
+    }
+}
+
+impl Search {
+    pub fn new(program: &[i64]) -> Search {
+        let mut machine = IntCodeMachine::new(program);
+        machine.set_instruction_budget(Some(DEFAULT_INSTRUCTION_BUDGET));
+        Search::with_machine(machine)
+    }
+
+    // Each candidate runs on a clone of `machine`, so its memory limit, instruction budget
+    // and loop detection apply to every run. Attached devices are not cloned. Unlike `new`,
+    // this sets no budget, so pass a machine without one to let runs go on indefinitely.
+    pub fn with_machine(machine: IntCodeMachine) -> Search {
+        Search {
+            machine,
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn evaluate(&self, candidate: Candidate) -> Evaluation {
+        evaluate(&self.machine, candidate)
+    }
+
+    pub fn find_first<I, P>(&self, candidates: I, predicate: P) -> SearchResult
+    where
+        I: IntoIterator<Item = Candidate>,
+        I::IntoIter: Send,
+        P: Fn(&Evaluation) -> bool + Sync,
+    {
+        let mut result = self.search(candidates, predicate, true);
+        result.matches.truncate(1);
+        result
+    }
+
+    pub fn find_all<I, P>(&self, candidates: I, predicate: P) -> SearchResult
+    where
+        I: IntoIterator<Item = Candidate>,
+        I::IntoIter: Send,
+        P: Fn(&Evaluation) -> bool + Sync,
+    {
+        self.search(candidates, predicate, false)
+    }
+
+    fn search<I, P>(&self, candidates: I, predicate: P, stop_at_first: bool) -> SearchResult
+    where
+        I: IntoIterator<Item = Candidate>,
+        I::IntoIter: Send,
+        P: Fn(&Evaluation) -> bool + Sync,
+    {
+        let candidates = Mutex::new(candidates.into_iter().enumerate());
+        let found = AtomicBool::new(false);
+        let results = Mutex::new((vec![], 0));
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let base = self.machine.clone();
+                let (candidates, found, results, predicate) =
+                    (&candidates, &found, &results, &predicate);
+                scope.spawn(move || loop {
+                    if stop_at_first && found.load(Ordering::SeqCst) {
+                        return;
+                    }
+
+                    let (index, candidate) = match candidates.lock().unwrap().next() {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    let evaluation = evaluate(&base, candidate);
+                    let matched = predicate(&evaluation);
+
+                    let mut results = results.lock().unwrap();
+                    results.1 += 1;
+                    if matched {
+                        found.store(true, Ordering::SeqCst);
+                        results.0.push((index, evaluation));
+                    }
+                });
+            }
+        });
+
+        let (mut matches, evaluated) = results.into_inner().unwrap();
+        matches.sort_by_key(|(index, _)| *index);
+        SearchResult {
+            matches: matches.into_iter().map(|(_, e)| e).collect(),
+            evaluated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grid, Candidate, Search, DEFAULT_INSTRUCTION_BUDGET};
+    use crate::intcode::patch::PatchSet;
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    // Stores the product of the values at 9 and 10 in 0, then prints it.
+    const MULTIPLY: [i64; 11] = [2, 9, 10, 0, 4, 0, 99, 0, 0, 0, 0];
+
+    #[test]
+    fn test_grid() {
+        let candidates: Vec<String> = grid(&[1, 2], 0..3)
+            .unwrap()
+            .map(|c| c.patches.to_string())
+            .collect();
+        assert_eq!(candidates.len(), 9);
+        assert_eq!(candidates[0], "1=0,2=0");
+        assert_eq!(candidates[1], "1=0,2=1");
+        assert_eq!(candidates[8], "1=2,2=2");
+        assert_eq!(grid(&[], 0..3).unwrap().count(), 0);
+        assert!(grid(&[1, 2, 3], 0..1 << 30).is_err());
+        assert!(grid(&[1], i64::MIN..i64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_find_first_and_all() {
+        let mut search = Search::new(&MULTIPLY);
+        for threads in [1, 4].iter() {
+            search.set_threads(*threads);
+            let result =
+                search.find_first(grid(&[9, 10], 0..10).unwrap(), |e| e.machine.peek(0) == 12);
+            assert_eq!(result.matches.len(), 1);
+            assert_eq!(result.matches[0].candidate.patches.to_string(), "9=2,10=6");
+            assert_eq!(result.matches[0].outputs, vec![12]);
+            assert!(result.evaluated >= 27 && result.evaluated < 100);
+        }
+
+        let result = search.find_all(grid(&[9, 10], 0..10).unwrap(), |e| e.outputs == vec![12]);
+        let found: Vec<String> = result
+            .matches
+            .iter()
+            .map(|e| e.candidate.patches.to_string())
+            .collect();
+        assert_eq!(found, vec!["9=2,10=6", "9=3,10=4", "9=4,10=3", "9=6,10=2"]);
+        assert_eq!(result.evaluated, 100);
+    }
+
+    #[test]
+    fn test_inputs_and_faults() {
+        // Prints its input doubled, and needs a second input it never gets.
+        let program = vec![3, 9, 102, 2, 9, 9, 4, 9, 3, 9, 99];
+        let search = Search::new(&program);
+        let candidates = (0..50).map(|v| Candidate::new(PatchSet::new(), vec![v]));
+        let result = search.find_all(candidates, |e| e.outputs[0] % 30 == 0);
+        assert_eq!(result.matches.len(), 4);
+        assert_eq!(result.matches[1].state, Ok(RunState::AwaitingInput));
+
+        let mut machine = IntCodeMachine::new(&MULTIPLY);
+        machine.set_memory_limit(Some(16));
+        let search = Search::with_machine(machine);
+        let mut patches = PatchSet::new();
+        patches.set(100, 1);
+        let evaluation = search.evaluate(Candidate::new(patches, vec![]));
+        assert!(matches!(
+            evaluation.state,
+            Err(IntCodeError::MemoryLimitExceeded { target: 100, .. })
+        ));
+
+        // Jumps to the target at 2: 0 loops forever and 3 halts.
+        let search = Search::new(&[1105, 1, 3, 99]);
+        let candidates = ["2=0", "2=3"]
+            .iter()
+            .map(|p| Candidate::new(p.parse().unwrap(), vec![]));
+        let result = search.find_all(candidates, |_| true);
+        assert!(matches!(
+            result.matches[0].state,
+            Err(IntCodeError::BudgetExhausted { executed, .. })
+                if executed == DEFAULT_INSTRUCTION_BUDGET
+        ));
+        assert_eq!(result.matches[1].state, Ok(RunState::Halted));
+    }
+}
@@ -0,0 +1,393 @@
+// Saving and restoring the full state of an `IntCodeMachine`.
+//
+//     intcode-snapshot 3
+//     backend dense
+//     program_length 16
+//     instruction 12
+//     relative_base 3
+//     input 1,2
+//     memory_limit 16777216
+//     registers 109,1,204,-1,...
+//
+// Paged machines store only their non-zero cells, as `cells 0:109,1:1,1000000000000:7`.
+// A machine without a memory limit has `memory_limit none`. Version 1 snapshots (dense,
+// without a `backend` line) and version 2 snapshots (without `program_length`) are still
+// accepted; their program is taken to be the memory allocated from address 0, and without a
+// `memory_limit` line they get `DEFAULT_MEMORY_LIMIT`.
+
+use super::memory::{Memory, MemoryBackend, DEFAULT_MEMORY_LIMIT};
+use super::IntCodeMachine;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+pub const SNAPSHOT_VERSION: u32 = 3;
+const MAGIC: &str = "intcode-snapshot";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    UnsupportedVersion(String),
+    Malformed { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported snapshot version {} (expected {})",
+                v, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Malformed { line, message } => {
+                write!(f, "malformed snapshot on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+fn join(values: impl Iterator<Item = i64>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn parse_cells(line: usize, text: &str) -> Result<Vec<(usize, i64)>, SnapshotError> {
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+
+    text.split(',')
+        .map(|cell| {
+            let malformed = || SnapshotError::Malformed {
+                line,
+                message: format!("'{}' is not an address:value pair", cell),
+            };
+            let colon = cell.find(':').ok_or_else(malformed)?;
+            let address = cell[..colon].parse::<usize>().map_err(|_| malformed())?;
+            let value = cell[colon + 1..].parse::<i64>().map_err(|_| malformed())?;
+            Ok((address, value))
+        })
+        .collect()
+}
+
+fn parse_list(line: usize, text: &str) -> Result<Vec<i64>, SnapshotError> {
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+
+    text.split(',')
+        .map(|v| {
+            v.parse::<i64>().map_err(|_| SnapshotError::Malformed {
+                line,
+                message: format!("'{}' is not a number", v),
+            })
+        })
+        .collect()
+}
+
+impl IntCodeMachine {
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{} {}", MAGIC, SNAPSHOT_VERSION)?;
+        match &self.registers {
+            Memory::Dense(_) => writeln!(writer, "backend dense")?,
+            Memory::Paged { .. } => writeln!(writer, "backend paged")?,
+        }
+        writeln!(writer, "program_length {}", self.program_length)?;
+        writeln!(writer, "instruction {}", self.instruction)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "input {}", join(self.input.iter().cloned()))?;
+        match self.memory_limit {
+            Some(limit) => writeln!(writer, "memory_limit {}", limit)?,
+            None => writeln!(writer, "memory_limit none")?,
+        }
+        match &self.registers {
+            Memory::Dense(registers) => {
+                writeln!(writer, "registers {}", join(registers.iter().cloned()))?
+            }
+            Memory::Paged { .. } => {
+                let cells: Vec<String> = self
+                    .registers
+                    .cells()
+                    .into_iter()
+                    .map(|(a, v)| format!("{}:{}", a, v))
+                    .collect();
+                writeln!(writer, "cells {}", cells.join(","))?
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn load<R: BufRead>(reader: R) -> Result<IntCodeMachine, SnapshotError> {
+        let mut instruction = None;
+        let mut relative_base = None;
+        let mut input = None;
+        let mut registers = None;
+        let mut registers_line = 0;
+        let mut backend = MemoryBackend::Dense;
+        let mut memory_limit = Some(DEFAULT_MEMORY_LIMIT);
+        let mut program_length = None;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line.trim(), ""),
+            };
+
+            if number == 1 {
+                if key != MAGIC {
+                    return Err(SnapshotError::Malformed {
+                        line: number,
+                        message: String::from("not an Intcode snapshot"),
+                    });
+                }
+
+                if !["1", "2"].contains(&value) && value != SNAPSHOT_VERSION.to_string() {
+                    return Err(SnapshotError::UnsupportedVersion(value.to_owned()));
+                }
+
+                continue;
+            }
+
+            let malformed = |message: String| SnapshotError::Malformed {
+                line: number,
+                message,
+            };
+            match key {
+                "" => (),
+                "backend" => {
+                    backend = match value {
+                        "dense" => MemoryBackend::Dense,
+                        "paged" => MemoryBackend::Paged,
+                        _ => return Err(malformed(format!("unknown backend '{}'", value))),
+                    }
+                }
+                "memory_limit" if value == "none" => memory_limit = None,
+                "memory_limit" => {
+                    memory_limit = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| malformed(format!("'{}' is not a cell count", value)))?,
+                    )
+                }
+                "program_length" => {
+                    program_length = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| malformed(format!("'{}' is not a cell count", value)))?,
+                    )
+                }
+                "instruction" => {
+                    instruction = Some(value.parse::<usize>().map_err(|_| {
+                        malformed(format!("'{}' is not an instruction pointer", value))
+                    })?)
+                }
+                "relative_base" => {
+                    relative_base = Some(
+                        value
+                            .parse::<i64>()
+                            .map_err(|_| malformed(format!("'{}' is not a number", value)))?,
+                    )
+                }
+                "input" => input = Some(parse_list(number, value)?),
+                "registers" => {
+                    registers_line = number;
+                    registers = Some(
+                        parse_list(number, value)?
+                            .into_iter()
+                            .enumerate()
+                            .collect::<Vec<_>>(),
+                    )
+                }
+                "cells" => {
+                    registers_line = number;
+                    registers = Some(parse_cells(number, value)?)
+                }
+                _ => return Err(malformed(format!("unknown field '{}'", key))),
+            }
+        }
+
+        let missing = |field: &str| SnapshotError::Malformed {
+            line: 0,
+            message: format!("missing field '{}'", field),
+        };
+
+        // Cells go through `write` so the memory limit applies while loading.
+        let mut machine = IntCodeMachine::with_memory(&[], backend);
+        machine.memory_limit = memory_limit;
+        for (address, value) in registers.ok_or_else(|| missing("registers"))? {
+            if machine.write(address, value).is_err() {
+                return Err(SnapshotError::Malformed {
+                    line: registers_line,
+                    message: format!("cell {} is beyond the memory limit", address),
+                });
+            }
+        }
+        machine.program_length =
+            program_length.unwrap_or_else(|| machine.registers.contiguous_len());
+        machine.decoded = vec![None; machine.program_length];
+        machine.instruction = instruction.ok_or_else(|| missing("instruction"))?;
+        machine.relative_base = relative_base.ok_or_else(|| missing("relative_base"))?;
+        machine.input = input
+            .ok_or_else(|| missing("input"))?
+            .into_iter()
+            .collect::<VecDeque<i64>>();
+
+        Ok(machine)
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<IntCodeMachine, SnapshotError> {
+        IntCodeMachine::load(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SnapshotError;
+    use crate::intcode::memory::MemoryBackend;
+    use crate::intcode::temp::TempFile;
+    use crate::intcode::{IntCodeMachine, RunState};
+    use std::collections::HashSet;
+
+    fn quine() -> Vec<i64> {
+        vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]
+    }
+
+    fn drain(machine: &mut IntCodeMachine) -> Vec<i64> {
+        let mut output = vec![];
+        while let Ok(RunState::Output(v)) = machine.run() {
+            output.push(v);
+        }
+
+        output
+    }
+
+    #[test]
+    fn test_clone_forks_execution() {
+        let mut machine = IntCodeMachine::new(&quine());
+        for _ in 0..5 {
+            machine.run().unwrap();
+        }
+
+        let mut fork = machine.clone();
+        assert_eq!(fork, machine);
+        assert_eq!(drain(&mut fork), quine()[5..].to_vec());
+        assert_eq!(drain(&mut machine), quine()[5..].to_vec());
+    }
+
+    #[test]
+    fn test_equality_ignores_grown_memory() {
+        let mut grown = IntCodeMachine::new(&[1, 0, 0, 0, 99]);
+        grown.registers.set(63, 0);
+        let plain = IntCodeMachine::new(&[1, 0, 0, 0, 99]);
+
+        let mut seen = HashSet::new();
+        seen.insert(plain.clone());
+        assert_eq!(grown, plain);
+        assert!(seen.contains(&grown));
+
+        grown.provide_input(1);
+        assert_ne!(grown, plain);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let mut machine = IntCodeMachine::new(&quine());
+        for _ in 0..3 {
+            machine.run().unwrap();
+        }
+        machine.provide_input(7);
+        machine.provide_input(-8);
+
+        let mut saved: Vec<u8> = vec![];
+        machine.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved.clone()).unwrap();
+        assert!(
+            text.starts_with(
+                "intcode-snapshot 3\nbackend dense\nprogram_length 16\ninstruction 4\nrelative_base 3\n"
+            )
+        );
+        assert!(text.contains("\ninput 7,-8\n"));
+
+        let mut restored = IntCodeMachine::load(saved.as_slice()).unwrap();
+        assert_eq!(restored, machine);
+        assert_eq!(restored.program_length, 16);
+        assert_eq!(drain(&mut restored), drain(&mut machine));
+
+        let file = TempFile::new("snapshot_test.txt");
+        machine.save_to_file(file.path()).unwrap();
+        assert_eq!(
+            IntCodeMachine::load_from_file(file.path()).unwrap(),
+            machine
+        );
+    }
+
+    #[test]
+    fn test_paged_snapshots_and_version_1() {
+        let mut machine =
+            IntCodeMachine::with_memory(&[1101, 0, 7, 1000000000000, 99], MemoryBackend::Paged);
+        machine.set_memory_limit(Some(1 << 16));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+
+        let mut saved: Vec<u8> = vec![];
+        machine.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved.clone()).unwrap();
+        assert!(text.contains("\nbackend paged\n"));
+        assert!(text.contains("\nmemory_limit 65536\n"));
+        assert!(text.ends_with("\ncells 0:1101,2:7,3:1000000000000,4:99,1000000000000:7\n"));
+
+        let restored = IntCodeMachine::load(saved.as_slice()).unwrap();
+        assert_eq!(restored.memory_backend(), MemoryBackend::Paged);
+        assert_eq!(restored, machine);
+
+        let text =
+            "intcode-snapshot 1\ninstruction 4\nrelative_base 0\ninput\nregisters 1101,0,7,0,99\n";
+        let restored = IntCodeMachine::load(text.as_bytes()).unwrap();
+        assert_eq!(restored.memory_backend(), MemoryBackend::Dense);
+        assert!(restored.is_halted());
+    }
+
+    #[test]
+    fn test_load_rejects_bad_snapshots() {
+        match IntCodeMachine::load("intcode-snapshot 4\n".as_bytes()) {
+            Err(SnapshotError::UnsupportedVersion(v)) => assert_eq!(v, "4"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let text = "intcode-snapshot 1\ninstruction 0\nrelative_base 0\ninput\nregisters 1,x\n";
+        match IntCodeMachine::load(text.as_bytes()) {
+            Err(SnapshotError::Malformed { line, .. }) => assert_eq!(line, 5),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let text = "intcode-snapshot 1\ninstruction 0\nregisters 99\n";
+        assert!(IntCodeMachine::load(text.as_bytes()).is_err());
+
+        let text = "intcode-snapshot 3\nbackend dense\ninstruction 0\nrelative_base 0\ninput\n\
+                    cells 0:99,1000000000000:1\n";
+        match IntCodeMachine::load(text.as_bytes()) {
+            Err(SnapshotError::Malformed { line, message }) => {
+                assert_eq!(line, 6);
+                assert_eq!(message, "cell 1000000000000 is beyond the memory limit");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
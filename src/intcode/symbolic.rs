@@ -0,0 +1,656 @@
+// Runs a program with some memory cells or inputs left as named symbols, so that writes and
+// outputs become expressions over them. Sums and constant multiples are kept in a canonical
+// linear form, which is enough to turn a day 2 style program into `mem[0] = a*noun + verb + c`
+// and solve for a target without running it again.
+//
+// Control flow must stay concrete: an opcode, jump, write target or relative base that
+// depends on a symbol stops the machine with `Unresolved`. Reading through a symbolic
+// address gives an opaque `mem[...]` value instead.
+
+use super::{IntCodeError, OpCode, ParameterMode, RunState};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+
+// `constant + sum(coefficient * symbol)`, with no zero coefficients.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct Linear {
+    pub constant: i64,
+    pub terms: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Expr {
+    Linear(Linear),
+    Load(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SymbolicError {
+    Machine(IntCodeError),
+    Unresolved { address: usize, value: Expr },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Machine(e) => write!(f, "{}", e),
+            SymbolicError::Unresolved { address, value } => write!(
+                f,
+                "instruction at address {} depends on the symbolic value {}",
+                address, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+impl From<IntCodeError> for SymbolicError {
+    fn from(e: IntCodeError) -> Self {
+        SymbolicError::Machine(e)
+    }
+}
+
+impl Linear {
+    pub fn constant(value: i64) -> Linear {
+        Linear {
+            constant: value,
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn symbol(name: &str) -> Linear {
+        let mut terms = BTreeMap::new();
+        terms.insert(name.to_owned(), 1);
+        Linear { constant: 0, terms }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.is_empty() {
+            true => Some(self.constant),
+            false => None,
+        }
+    }
+
+    pub fn coefficient(&self, symbol: &str) -> i64 {
+        self.terms.get(symbol).copied().unwrap_or(0)
+    }
+
+    pub fn evaluate(&self, values: &BTreeMap<String, i64>) -> Option<i64> {
+        self.terms.iter().try_fold(self.constant, |sum, (name, c)| {
+            c.checked_mul(*values.get(name)?)?.checked_add(sum)
+        })
+    }
+
+    fn checked_add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (name, c) in other.terms.iter() {
+            let total = sum.coefficient(name).checked_add(*c)?;
+            match total {
+                0 => sum.terms.remove(name),
+                _ => sum.terms.insert(name.clone(), total),
+            };
+        }
+        Some(sum)
+    }
+
+    fn checked_scale(&self, factor: i64) -> Option<Linear> {
+        if factor == 0 {
+            return Some(Linear::constant(0));
+        }
+
+        let mut terms = BTreeMap::new();
+        for (name, c) in self.terms.iter() {
+            terms.insert(name.clone(), c.checked_mul(factor)?);
+        }
+        Some(Linear {
+            constant: self.constant.checked_mul(factor)?,
+            terms,
+        })
+    }
+
+    // Every assignment of the symbols, drawn from `domains`, for which the expression equals
+    // `target`. All symbols but the last are enumerated and the last is solved for, so the
+    // cost is the product of the other domains' sizes. A symbol without a domain has no
+    // solutions.
+    pub fn solve(&self, target: i64, domains: &[(&str, Range<i64>)]) -> Vec<BTreeMap<String, i64>> {
+        let domain = |name: &str| {
+            domains
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, range)| range.clone())
+        };
+        let mut names: Vec<&String> = self.terms.keys().collect();
+        let last = match names.pop() {
+            Some(last) => last,
+            None if self.constant == target => return vec![BTreeMap::new()],
+            None => return vec![],
+        };
+        let (last_domain, last_coefficient) = match domain(last) {
+            Some(range) => (range, self.terms[last]),
+            None => return vec![],
+        };
+
+        let mut ranges = vec![];
+        for name in names.iter() {
+            match domain(name) {
+                Some(range) if !range.is_empty() => ranges.push(range),
+                _ => return vec![],
+            }
+        }
+
+        let mut solutions = vec![];
+        let mut values: Vec<i64> = ranges.iter().map(|r| r.start).collect();
+        'search: loop {
+            let mut assignment: BTreeMap<String, i64> = names
+                .iter()
+                .zip(values.iter())
+                .map(|(n, v)| ((*n).clone(), *v))
+                .collect();
+            assignment.insert(last.clone(), 0);
+            if let Some(rest) = self
+                .evaluate(&assignment)
+                .and_then(|partial| target.checked_sub(partial))
+            {
+                if let (Some(0), Some(value)) = (
+                    rest.checked_rem(last_coefficient),
+                    rest.checked_div(last_coefficient),
+                ) {
+                    if last_domain.contains(&value) {
+                        assignment.insert(last.clone(), value);
+                        solutions.push(assignment);
+                    }
+                }
+            }
+
+            // Advance the other symbols like an odometer.
+            for i in (0..values.len()).rev() {
+                values[i] += 1;
+                if values[i] < ranges[i].end {
+                    continue 'search;
+                }
+                values[i] = ranges[i].start;
+            }
+            return solutions;
+        }
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, c)) in self.terms.iter().enumerate() {
+            match (i, *c < 0) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => (),
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            match c.unsigned_abs() {
+                1 => write!(f, "{}", name)?,
+                n => write!(f, "{}*{}", n, name)?,
+            }
+        }
+
+        match (self.terms.is_empty(), self.constant) {
+            (true, c) => write!(f, "{}", c),
+            (false, 0) => Ok(()),
+            (false, c) if c < 0 => write!(f, " - {}", c.unsigned_abs()),
+            (false, c) => write!(f, " + {}", c),
+        }
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(value: i64) -> Self {
+        Expr::constant(value)
+    }
+}
+
+impl Expr {
+    pub fn constant(value: i64) -> Expr {
+        Expr::Linear(Linear::constant(value))
+    }
+
+    pub fn symbol(name: &str) -> Expr {
+        Expr::Linear(Linear::symbol(name))
+    }
+
+    pub fn linear(&self) -> Option<&Linear> {
+        match self {
+            Expr::Linear(linear) => Some(linear),
+            _ => None,
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        self.linear().and_then(Linear::as_constant)
+    }
+
+    pub fn sum(left: Expr, right: Expr) -> Expr {
+        if let (Some(a), Some(b)) = (left.linear(), right.linear()) {
+            if let Some(sum) = a.checked_add(b) {
+                return Expr::Linear(sum);
+            }
+        }
+        Expr::Add(Box::new(left), Box::new(right))
+    }
+
+    pub fn product(left: Expr, right: Expr) -> Expr {
+        let product = match (left.linear(), right.linear()) {
+            (Some(a), Some(b)) => match (a.as_constant(), b.as_constant()) {
+                (Some(k), _) => b.checked_scale(k),
+                (_, Some(k)) => a.checked_scale(k),
+                _ => None,
+            },
+            _ => None,
+        };
+        match product {
+            Some(product) => Expr::Linear(product),
+            None => Expr::Mul(Box::new(left), Box::new(right)),
+        }
+    }
+
+    pub fn less_than(left: Expr, right: Expr) -> Expr {
+        match Expr::difference(&left, &right) {
+            Some(d) => Expr::constant((d < 0) as i64),
+            None => Expr::LessThan(Box::new(left), Box::new(right)),
+        }
+    }
+
+    pub fn equals(left: Expr, right: Expr) -> Expr {
+        match Expr::difference(&left, &right) {
+            Some(d) => Expr::constant((d == 0) as i64),
+            None if left == right => Expr::constant(1),
+            None => Expr::Equals(Box::new(left), Box::new(right)),
+        }
+    }
+
+    // `left - right` when it does not depend on any symbol.
+    fn difference(left: &Expr, right: &Expr) -> Option<i64> {
+        let negated = right.linear()?.checked_scale(-1)?;
+        left.linear()?.checked_add(&negated)?.as_constant()
+    }
+
+    // Operands of `*` that print as more than one term need parentheses.
+    fn fmt_factor(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let simple = match self {
+            Expr::Linear(l) => l.terms.is_empty() || (l.terms.len() == 1 && l.constant == 0),
+            Expr::Add(..) => false,
+            _ => true,
+        };
+        match simple {
+            true => write!(f, "{}", self),
+            false => write!(f, "({})", self),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Linear(linear) => write!(f, "{}", linear),
+            Expr::Load(address) => write!(f, "mem[{}]", address),
+            Expr::Add(a, b) => write!(f, "{} + {}", a, b),
+            Expr::Mul(a, b) => {
+                a.fmt_factor(f)?;
+                write!(f, "*")?;
+                b.fmt_factor(f)
+            }
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicMachine {
+    instruction: usize,
+    relative_base: i64,
+    // Only cells that have been loaded or written are stored, so writes to huge addresses
+    // stay cheap. `len` is one past the highest of them.
+    memory: BTreeMap<usize, Expr>,
+    len: usize,
+    input: VecDeque<Expr>,
+    instruction_count: u64,
+    instruction_budget: Option<u64>,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[i64]) -> SymbolicMachine {
+        SymbolicMachine {
+            instruction: 0,
+            relative_base: 0,
+            memory: program
+                .iter()
+                .enumerate()
+                .map(|(a, v)| (a, Expr::constant(*v)))
+                .collect(),
+            len: program.len(),
+            input: VecDeque::new(),
+            instruction_count: 0,
+            instruction_budget: None,
+        }
+    }
+
+    // Replaces the cell at `address` with the symbol `name`, e.g. day 2's noun at 1.
+    pub fn symbolize(&mut self, address: usize, name: &str) {
+        self.store(address, Expr::symbol(name));
+    }
+
+    pub fn provide_input(&mut self, input: Expr) {
+        self.input.push_back(input);
+    }
+
+    pub fn peek(&self, address: usize) -> Expr {
+        self.load(address)
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    pub fn run(&mut self) -> Result<RunState<Expr>, SymbolicError> {
+        loop {
+            match self.step() {
+                Ok(Some(v)) => return Ok(RunState::Output(v)),
+                Ok(None) => (),
+                Err(SymbolicError::Machine(IntCodeError::ProgramComplete)) => {
+                    return Ok(RunState::Halted)
+                }
+                Err(SymbolicError::Machine(IntCodeError::NeedInput)) => {
+                    return Ok(RunState::AwaitingInput)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn run_program(&mut self) -> Result<Expr, SymbolicError> {
+        loop {
+            if let Some(v) = self.step()? {
+                return Ok(v);
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Option<Expr>, SymbolicError> {
+        if let Some(budget) = self.instruction_budget {
+            if self.instruction_count >= budget {
+                return Err(SymbolicError::Machine(IntCodeError::BudgetExhausted {
+                    address: self.instruction,
+                    executed: self.instruction_count,
+                }));
+            }
+        }
+
+        let result = self.execute();
+        if result.is_ok() {
+            self.instruction_count += 1;
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Option<Expr>, SymbolicError> {
+        let address = self.instruction;
+        let word = self.concrete(self.load(address))?;
+        let opcode = match OpCode::try_from_instruction(word) {
+            Some(opcode) => opcode,
+            None => {
+                return Err(IntCodeError::InvalidOpcode {
+                    address,
+                    value: word,
+                }
+                .into())
+            }
+        };
+        let mut modes = [ParameterMode::Position; 3];
+        for (i, mode) in modes.iter_mut().enumerate() {
+            let number = i as i64 + 1;
+            *mode = match ParameterMode::try_from_instruction_and_number(word, number) {
+                Some(mode) => mode,
+                None => {
+                    return Err(IntCodeError::InvalidParameterMode {
+                        address,
+                        value: word,
+                        parameter: number,
+                    }
+                    .into())
+                }
+            };
+        }
+
+        let step: usize;
+        match opcode {
+            OpCode::End => return Err(IntCodeError::ProgramComplete.into()),
+            OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => {
+                step = 4;
+                let left = self.parameter(1, modes[0])?;
+                let right = self.parameter(2, modes[1])?;
+                let target = self.target(3, modes[2])?;
+                let value = match opcode {
+                    OpCode::Add => Expr::sum(left, right),
+                    OpCode::Multiply => Expr::product(left, right),
+                    OpCode::LessThan => Expr::less_than(left, right),
+                    _ => Expr::equals(left, right),
+                };
+                self.store(target, value);
+            }
+            OpCode::Input => {
+                step = 2;
+                let target = self.target(1, modes[0])?;
+                let input = match self.input.pop_front() {
+                    Some(v) => v,
+                    None => return Err(IntCodeError::NeedInput.into()),
+                };
+                self.store(target, input);
+            }
+            OpCode::Output => {
+                let value = self.parameter(1, modes[0])?;
+                self.instruction = self.offset(2)?;
+                return Ok(Some(value));
+            }
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                step = 3;
+                let condition = self.parameter(1, modes[0])?;
+                let condition = self.concrete(condition)?;
+                if (condition != 0) == (opcode == OpCode::JumpIfTrue) {
+                    let target = self.parameter(2, modes[1])?;
+                    let target = self.concrete(target)?;
+                    if target < 0 || target as usize >= self.len {
+                        return Err(IntCodeError::JumpOutOfRange { address, target }.into());
+                    }
+                    self.instruction = target as usize;
+                    return Ok(None);
+                }
+            }
+            OpCode::RelativeBaseOffset => {
+                step = 2;
+                let offset = self.parameter(1, modes[0])?;
+                let offset = self.concrete(offset)?;
+                self.relative_base = match self.relative_base.checked_add(offset) {
+                    Some(base) => base,
+                    None => return Err(IntCodeError::Overflow { address }.into()),
+                };
+            }
+        }
+
+        self.instruction = self.offset(step)?;
+        Ok(None)
+    }
+
+    // The address `n` cells after the current instruction.
+    fn offset(&self, n: usize) -> Result<usize, SymbolicError> {
+        match self.instruction.checked_add(n) {
+            Some(address) => Ok(address),
+            None => Err(IntCodeError::Overflow {
+                address: self.instruction,
+            }
+            .into()),
+        }
+    }
+
+    fn load(&self, address: usize) -> Expr {
+        match self.memory.get(&address) {
+            Some(v) => v.clone(),
+            None => Expr::constant(0),
+        }
+    }
+
+    fn store(&mut self, address: usize, value: Expr) {
+        self.len = self.len.max(address.saturating_add(1));
+        self.memory.insert(address, value);
+    }
+
+    fn concrete(&self, value: Expr) -> Result<i64, SymbolicError> {
+        match value.as_constant() {
+            Some(v) => Ok(v),
+            None => Err(SymbolicError::Unresolved {
+                address: self.instruction,
+                value,
+            }),
+        }
+    }
+
+    fn address(&self, target: i64) -> Result<usize, SymbolicError> {
+        match target < 0 {
+            true => Err(IntCodeError::NegativeAddress {
+                address: self.instruction,
+                target,
+            }
+            .into()),
+            false => Ok(target as usize),
+        }
+    }
+
+    fn parameter(&self, number: usize, mode: ParameterMode) -> Result<Expr, SymbolicError> {
+        let value = self.load(self.offset(number)?);
+        let pointer = match mode {
+            ParameterMode::Immediate => return Ok(value),
+            ParameterMode::Position => value,
+            ParameterMode::Relative => Expr::sum(value, Expr::constant(self.relative_base)),
+        };
+        match pointer.as_constant() {
+            Some(target) => Ok(self.load(self.address(target)?)),
+            None => Ok(Expr::Load(Box::new(pointer))),
+        }
+    }
+
+    fn target(&self, number: usize, mode: ParameterMode) -> Result<usize, SymbolicError> {
+        let value = self.load(self.offset(number)?);
+        let pointer = match mode {
+            ParameterMode::Position => value,
+            ParameterMode::Relative => Expr::sum(value, Expr::constant(self.relative_base)),
+            ParameterMode::Immediate => {
+                return Err(IntCodeError::ImmediateWrite {
+                    address: self.instruction,
+                    parameter: number as i64,
+                }
+                .into())
+            }
+        };
+        let target = self.concrete(pointer)?;
+        self.address(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, Linear, SymbolicError, SymbolicMachine};
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    // Laid out like day 2: the first instruction reads through the noun and verb, and the
+    // rest compute `mem[0] = 1000*noun + verb + 42`.
+    const DAY2: [i64; 19] = [
+        1, 0, 0, 3, 2, 1, 17, 3, 1, 3, 2, 3, 1, 3, 18, 0, 99, 1000, 42,
+    ];
+
+    #[test]
+    fn test_linear_memory() {
+        let mut machine = SymbolicMachine::new(&DAY2);
+        machine.symbolize(1, "noun");
+        machine.symbolize(2, "verb");
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+
+        let result = machine.peek(0);
+        assert_eq!(result.to_string(), "1000*noun + verb + 42");
+        let solutions = result
+            .linear()
+            .unwrap()
+            .solve(12076, &[("noun", 0..100), ("verb", 0..100)]);
+        assert_eq!(solutions.len(), 1);
+        assert_eq!((solutions[0]["noun"], solutions[0]["verb"]), (12, 34));
+
+        let mut program = DAY2.to_vec();
+        program[1] = 12;
+        program[2] = 34;
+        let mut concrete = IntCodeMachine::new(&program);
+        assert_eq!(concrete.run(), Ok(RunState::Halted));
+        assert_eq!(concrete.peek(0), 12076);
+    }
+
+    #[test]
+    fn test_symbolic_inputs() {
+        // Prints 3*x - 7, then whether x equals y, then x*y.
+        let program = vec![
+            3, 100, 3, 101, 1002, 100, 3, 102, 1001, 102, -7, 102, 4, 102, 8, 100, 101, 103, 4,
+            103, 2, 100, 101, 104, 4, 104, 99,
+        ];
+        let mut machine = SymbolicMachine::new(&program);
+        machine.provide_input(Expr::symbol("x"));
+        machine.provide_input(Expr::sum(Expr::symbol("x"), Expr::from(1)));
+
+        let outputs: Vec<String> = (0..3)
+            .map(|_| machine.run_program().unwrap().to_string())
+            .collect();
+        assert_eq!(outputs, vec!["3*x - 7", "0", "x*(x + 1)"]);
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn test_unresolved_control_flow() {
+        // Jumps to 0 if the input is non-zero.
+        let program = vec![3, 7, 1005, 7, 0, 99, 0, 0];
+        let mut machine = SymbolicMachine::new(&program);
+        assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+        machine.provide_input(Expr::symbol("x"));
+        assert_eq!(
+            machine.run(),
+            Err(SymbolicError::Unresolved {
+                address: 2,
+                value: Expr::symbol("x")
+            })
+        );
+        assert_eq!(
+            machine.run().unwrap_err().to_string(),
+            "instruction at address 2 depends on the symbolic value x"
+        );
+    }
+
+    #[test]
+    fn test_large_values() {
+        // Writes to address 10^12, then moves the relative base past the largest word.
+        let program = vec![1101, 7, 0, 1000000000000, 109, i64::MAX, 109, 1, 99];
+        let mut machine = SymbolicMachine::new(&program);
+        assert_eq!(
+            machine.run(),
+            Err(SymbolicError::Machine(IntCodeError::Overflow {
+                address: 6
+            }))
+        );
+        assert_eq!(machine.peek(1000000000000), Expr::constant(7));
+
+        // -x = i64::MIN has no solution in 64 bits.
+        let negated = Linear::symbol("x").checked_scale(-1).unwrap();
+        assert!(negated.solve(i64::MIN, &[("x", -1..2)]).is_empty());
+    }
+}
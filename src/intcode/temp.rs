@@ -0,0 +1,26 @@
+// A per-process file in the temporary directory for tests, removed when dropped so a
+// failed assertion does not leave it behind.
+
+use std::path::{Path, PathBuf};
+
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub(crate) fn new(name: &str) -> TempFile {
+        TempFile {
+            path: std::env::temp_dir().join(format!("aoc_intcode_{}_{}", std::process::id(), name)),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
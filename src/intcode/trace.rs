@@ -0,0 +1,273 @@
+use super::word::Word;
+use super::{IntCodeError, OpCode};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// One executed instruction. `operands` holds the value of every parameter after its mode
+// has been applied: the value read for inputs, and the resolved address for write targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub address: usize,
+    pub opcode: OpCode,
+    pub operands: Vec<i64>,
+    pub writes: Vec<(usize, i64)>,
+    pub relative_base: Option<i64>,
+    pub output: Option<i64>,
+}
+
+impl TraceEvent {
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|v| v.to_string()).collect();
+        let writes: Vec<String> = self
+            .writes
+            .iter()
+            .map(|(a, v)| format!("[{},{}]", a, v))
+            .collect();
+        let optional = |v: Option<i64>| match v {
+            Some(v) => v.to_string(),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"address\":{},\"opcode\":\"{}\",\"operands\":[{}],\"writes\":[{}],\"relative_base\":{},\"output\":{}}}",
+            self.address,
+            self.opcode.mnemonic(),
+            operands.join(","),
+            writes.join(","),
+            optional(self.relative_base),
+            optional(self.output)
+        )
+    }
+}
+
+pub trait TraceSink {
+    fn record(&mut self, event: &TraceEvent);
+}
+
+impl<F: FnMut(&TraceEvent)> TraceSink for F {
+    fn record(&mut self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+// Keeps the last `capacity` instructions. Clones share the same buffer, so keep one
+// handle to inspect after giving the other to `IntCodeMachine::set_tracer`.
+#[derive(Clone)]
+pub struct RingBuffer {
+    capacity: usize,
+    events: Arc<Mutex<VecDeque<TraceEvent>>>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            capacity,
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl TraceSink for RingBuffer {
+    fn record(&mut self, event: &TraceEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+    }
+}
+
+// Writes one JSON object per executed instruction. Write errors stop the trace rather
+// than the program being traced.
+pub struct JsonLinesWriter<W: Write> {
+    writer: Option<W>,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> JsonLinesWriter<W> {
+        JsonLinesWriter {
+            writer: Some(writer),
+        }
+    }
+}
+
+impl JsonLinesWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(JsonLinesWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> TraceSink for JsonLinesWriter<W> {
+    fn record(&mut self, event: &TraceEvent) {
+        if let Some(writer) = self.writer.as_mut() {
+            if writeln!(writer, "{}", event.to_json()).is_err() {
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for JsonLinesWriter<W> {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+// The machine's attachment point for a sink. Forked (cloned) machines start untraced.
+#[derive(Default)]
+pub(crate) struct TraceHook {
+    sink: Option<Box<dyn TraceSink + Send>>,
+    current: Option<TraceEvent>,
+}
+
+impl TraceHook {
+    pub(crate) fn attach(&mut self, sink: Box<dyn TraceSink + Send>) {
+        self.sink = Some(sink);
+    }
+
+    pub(crate) fn detach(&mut self) -> Option<Box<dyn TraceSink + Send>> {
+        self.current = None;
+        self.sink.take()
+    }
+
+    pub(crate) fn begin(&mut self, address: usize, opcode: OpCode) {
+        if self.sink.is_some() {
+            self.current = Some(TraceEvent {
+                address,
+                opcode,
+                operands: vec![],
+                writes: vec![],
+                relative_base: None,
+                output: None,
+            });
+        }
+    }
+
+    pub(crate) fn operand<W: Word>(&mut self, value: &W) {
+        if let Some(event) = self.current.as_mut() {
+            event.operands.push(value.saturating_i64());
+        }
+    }
+
+    pub(crate) fn write<W: Word>(&mut self, address: usize, value: &W) {
+        if let Some(event) = self.current.as_mut() {
+            event.writes.push((address, value.saturating_i64()));
+        }
+    }
+
+    pub(crate) fn relative_base(&mut self, value: i64) {
+        if let Some(event) = self.current.as_mut() {
+            event.relative_base = Some(value);
+        }
+    }
+
+    // Only instructions that ran to completion are recorded.
+    pub(crate) fn finish<W: Word>(&mut self, result: &Result<Option<W>, IntCodeError>) {
+        if let Some(mut event) = self.current.take() {
+            if let Ok(output) = result {
+                event.output = output.as_ref().map(|v| v.saturating_i64());
+                if let Some(sink) = self.sink.as_mut() {
+                    sink.record(&event);
+                }
+            }
+        }
+    }
+}
+
+impl Clone for TraceHook {
+    fn clone(&self) -> Self {
+        TraceHook::default()
+    }
+}
+
+impl fmt::Debug for TraceHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TraceHook {{ attached: {} }}", self.sink.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonLinesWriter, RingBuffer, TraceEvent};
+    use crate::intcode::temp::TempFile;
+    use crate::intcode::{IntCodeError, IntCodeMachine, OpCode, RunState};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_ring_buffer_keeps_last_instructions() {
+        // Reads a value, outputs it, then jumps to a negative address.
+        let program = vec![3, 9, 4, 9, 109, 7, 1106, 0, -1, 0];
+        let ring = RingBuffer::new(3);
+
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_tracer(ring.clone());
+        machine.provide_input(42);
+
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::JumpOutOfRange {
+                address: 6,
+                target: -1
+            })
+        );
+
+        let events = ring.events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            TraceEvent {
+                address: 0,
+                opcode: OpCode::Input,
+                operands: vec![9],
+                writes: vec![(9, 42)],
+                relative_base: None,
+                output: None,
+            }
+        );
+        assert_eq!(events[1].output, Some(42));
+        assert_eq!(events[2].relative_base, Some(7));
+    }
+
+    #[test]
+    fn test_json_lines_and_closure_sinks() {
+        let program = vec![1101, 2, 3, 5, 99, 0];
+
+        let file = TempFile::new("trace_test.jsonl");
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_tracer(JsonLinesWriter::create(file.path()).unwrap());
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        drop(machine);
+
+        assert_eq!(
+            std::fs::read_to_string(file.path()).unwrap(),
+            "{\"address\":0,\"opcode\":\"add\",\"operands\":[2,3,5],\"writes\":[[5,5]],\"relative_base\":null,\"output\":null}\n"
+        );
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = seen.clone();
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_tracer(move |e: &TraceEvent| recorder.lock().unwrap().push(e.address));
+        machine.run().unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![0]);
+
+        let mut fork = machine.clone();
+        fork.run().unwrap();
+        assert!(machine.clear_tracer().is_some());
+        assert!(fork.clear_tracer().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+// Detects programs that can never finish. Between two I/O instructions a program is fully
+// determined by its instruction pointer, relative base and memory, so seeing the same triple
+// twice means it will loop forever. Memory is compared by a hash that `IntCodeMachine::write`
+// keeps up to date, so a repeat is only a false alarm if two memory images hash equal.
+//
+// Rather than remembering every state, the detector uses Brent's cycle detection: it keeps
+// one saved state and replaces it after 1, 2, 4, ... further instructions. Memory use is
+// constant, and a loop of length L entered after N instructions is caught within about
+// 2 * max(N, L) + L instructions of that.
+
+use super::memory::Memory;
+use super::word::Word;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Zero cells contribute nothing, so the hash does not depend on how far memory has grown.
+fn cell_hash<W: Word>(address: usize, value: &W) -> u64 {
+    if value.is_zero() {
+        return 0;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    address.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LoopDetector {
+    memory_hash: u64,
+    saved: Option<(usize, i64, u64)>,
+    since_saved: u64,
+    period: u64,
+}
+
+impl LoopDetector {
+    pub(crate) fn new<W: Word>(memory: &Memory<W>) -> LoopDetector {
+        LoopDetector {
+            memory_hash: memory
+                .cells()
+                .into_iter()
+                .fold(0, |hash, (a, v)| hash.wrapping_add(cell_hash(a, &v))),
+            saved: None,
+            since_saved: 0,
+            period: 1,
+        }
+    }
+
+    pub(crate) fn write<W: Word>(&mut self, address: usize, old: &W, new: &W) {
+        self.memory_hash = self
+            .memory_hash
+            .wrapping_sub(cell_hash(address, old))
+            .wrapping_add(cell_hash(address, new));
+    }
+
+    pub(crate) fn state(&self, instruction: usize, relative_base: i64) -> (usize, i64, u64) {
+        (instruction, relative_base, self.memory_hash)
+    }
+
+    pub(crate) fn seen(&self, state: &(usize, i64, u64)) -> bool {
+        self.saved.as_ref() == Some(state)
+    }
+
+    pub(crate) fn record(&mut self, state: (usize, i64, u64)) {
+        self.since_saved += 1;
+        if self.saved.is_none() || self.since_saved >= self.period {
+            self.saved = Some(state);
+            self.since_saved = 0;
+            self.period = self.period.saturating_mul(2);
+        }
+    }
+
+    pub(crate) fn io(&mut self) {
+        self.saved = None;
+        self.since_saved = 0;
+        self.period = 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    #[test]
+    fn test_instruction_budget() {
+        // Counts down from 3, printing each value, then spins forever.
+        let program = vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 1105, 1, 9, 3];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_instruction_budget(Some(10));
+
+        assert_eq!(machine.run(), Ok(RunState::Output(3)));
+        assert_eq!(machine.run(), Ok(RunState::Output(2)));
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::BudgetExhausted {
+                address: 9,
+                executed: 10
+            })
+        );
+        assert_eq!(machine.instruction_count(), 10);
+
+        machine.set_instruction_budget(Some(20));
+        assert!(machine.run().is_err());
+        assert_eq!(machine.instruction_count(), 20);
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let program = vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 1105, 1, 9, 3];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_loop_detection(true);
+        for v in (1..=3).rev() {
+            assert_eq!(machine.run(), Ok(RunState::Output(v)));
+        }
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::InfiniteLoop { address: 9 })
+        );
+
+        // The counter at 100 keeps changing memory, so this loop is not flagged until the
+        // budget runs out.
+        let program = vec![1001, 100, 1, 100, 1105, 1, 0];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_loop_detection(true);
+        machine.set_instruction_budget(Some(1000));
+        assert_eq!(
+            machine.run(),
+            Err(IntCodeError::BudgetExhausted {
+                address: 0,
+                executed: 1000
+            })
+        );
+
+        // Waiting for input in between is I/O, not a loop.
+        let program = vec![3, 100, 1105, 1, 0];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.set_loop_detection(true);
+        for _ in 0..3 {
+            machine.provide_input(5);
+            assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+        }
+    }
+
+    #[test]
+    fn test_long_loops_are_detected() {
+        // Counts the cell at 100 down from 50000, then loops over a two-instruction body
+        // forever without changing memory.
+        let program = vec![
+            1001, 100, -1, 100, 1005, 100, 0, 1101, 0, 0, 101, 1105, 1, 7,
+        ];
+        let mut machine = IntCodeMachine::new(&program);
+        machine.poke(100, 50000).unwrap();
+        machine.set_loop_detection(true);
+        assert!(matches!(
+            machine.run(),
+            Err(IntCodeError::InfiniteLoop { .. })
+        ));
+        assert!(machine.instruction_count() < 2 * 3 * 50000);
+    }
+}
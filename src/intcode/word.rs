@@ -0,0 +1,158 @@
+// The value type an `IntCodeMachine` computes with. `i64` is the default and reports
+// overflow as an error; `i128` and `BigInt` let programs build much larger numbers.
+//
+// Addresses, jump targets and the relative base are always 64-bit; using a word that does
+// not fit in an `i64` as one is an `Overflow`.
+
+pub use num::BigInt;
+use num::{ToPrimitive, Zero};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+pub trait Word: Clone + Debug + Display + Eq + Ord + Hash + Send + 'static {
+    fn from_i64(value: i64) -> Self;
+    fn to_i64(&self) -> Option<i64>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn zero() -> Self {
+        Self::from_i64(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    fn saturating_i64(&self) -> i64 {
+        match self.to_i64() {
+            Some(v) => v,
+            None if *self > Self::zero() => i64::MAX,
+            None => i64::MIN,
+        }
+    }
+}
+
+impl Word for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+}
+
+impl Word for i128 {
+    fn from_i64(value: i64) -> Self {
+        value as i128
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+}
+
+impl Word for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+    use crate::intcode::memory::MemoryBackend;
+    use crate::intcode::{IntCodeError, IntCodeMachine, RunState};
+
+    // Squares the value at 15 as many times as the counter at 16 says, then prints it.
+    fn squares(times: i64) -> Vec<i64> {
+        vec![
+            1001, 16, -1, 16, 2, 15, 15, 15, 1005, 16, 0, 4, 15, 99, 0, 3, times,
+        ]
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let mut machine = IntCodeMachine::new(&squares(6));
+        assert_eq!(machine.run(), Err(IntCodeError::Overflow { address: 4 }));
+    }
+
+    #[test]
+    fn test_wider_words() {
+        let mut machine: IntCodeMachine<i128> =
+            IntCodeMachine::from_program(&squares(6), MemoryBackend::Dense);
+        assert_eq!(machine.run(), Ok(RunState::Output(3i128.pow(64))));
+
+        let mut machine: IntCodeMachine<i128> =
+            IntCodeMachine::from_program(&squares(7), MemoryBackend::Dense);
+        assert_eq!(machine.run(), Err(IntCodeError::Overflow { address: 4 }));
+
+        let mut machine: IntCodeMachine<BigInt> =
+            IntCodeMachine::from_program(&squares(7), MemoryBackend::Paged);
+        let expected = (0..128).fold(BigInt::from(1), |v, _| v * 3);
+        assert_eq!(machine.run(), Ok(RunState::Output(expected)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn test_wide_input_and_comparisons() {
+        // Reads two values and prints 1 if the first is less than the second.
+        let program = vec![3, 11, 3, 12, 7, 11, 12, 13, 4, 13, 99];
+        let huge = BigInt::from(i64::MAX) * BigInt::from(1000);
+        let mut machine: IntCodeMachine<BigInt> =
+            IntCodeMachine::from_program(&program, MemoryBackend::Dense);
+        machine.provide_input(huge.clone());
+        machine.provide_input(huge + 1);
+        assert_eq!(machine.get_output(), Some(BigInt::from(1)));
+    }
+
+    #[test]
+    fn test_address_overflow() {
+        let mut machine = IntCodeMachine::new(&[109, i64::MAX, 109, 1, 99]);
+        assert_eq!(machine.run(), Err(IntCodeError::Overflow { address: 2 }));
+
+        // Jumps to 2^64, and reads from 2^64.
+        for (program, cell) in [(vec![1105, 1, 0, 99], 2), (vec![4, 0, 99], 1)].iter() {
+            let mut machine: IntCodeMachine<i128> =
+                IntCodeMachine::from_program(program, MemoryBackend::Dense);
+            machine.poke(*cell, 1i128 << 64).unwrap();
+            assert_eq!(machine.run(), Err(IntCodeError::Overflow { address: 0 }));
+        }
+    }
+}
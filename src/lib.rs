@@ -26,201 +26,906 @@ pub mod utils {
 }
 
 pub mod intcode {
+    pub mod ascii;
+    pub mod asm;
+    pub mod backtrace;
+    pub mod cfg;
+    pub mod debugger;
+    pub mod decompile;
+    pub mod disasm;
+    pub mod io;
+    pub mod loader;
+    pub mod memory;
+    pub mod network;
+    pub mod patch;
+    pub mod pipeline;
+    pub mod profile;
+    pub mod protect;
+    pub mod search;
+    pub mod snapshot;
+    pub mod symbolic;
+    #[cfg(test)]
+    mod temp;
+    pub mod trace;
+    pub mod watchdog;
+    pub mod word;
+
     use std::collections::VecDeque;
+    use std::fmt;
+    use std::hash::{Hash, Hasher};
+    use std::sync::Arc;
+    use word::Word;
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Clone)]
     pub enum IntCodeError {
         NeedInput,
         ProgramComplete,
+        InvalidOpcode {
+            address: usize,
+            value: i64,
+        },
+        InvalidParameterMode {
+            address: usize,
+            value: i64,
+            parameter: i64,
+        },
+        ImmediateWrite {
+            address: usize,
+            parameter: i64,
+        },
+        NegativeAddress {
+            address: usize,
+            target: i64,
+        },
+        JumpOutOfRange {
+            address: usize,
+            target: i64,
+        },
+        MemoryLimitExceeded {
+            address: usize,
+            target: usize,
+            limit: usize,
+        },
+        Device {
+            address: usize,
+            message: String,
+        },
+        BudgetExhausted {
+            address: usize,
+            executed: u64,
+        },
+        InfiniteLoop {
+            address: usize,
+        },
+        Overflow {
+            address: usize,
+        },
+        ProtectionViolation {
+            address: usize,
+            target: usize,
+            access: protect::Access,
+        },
+        // A fault with the call stack at the time it happened, see `set_backtraces`.
+        Traced {
+            error: Box<IntCodeError>,
+            backtrace: backtrace::Backtrace,
+        },
+    }
+
+    impl IntCodeError {
+        // The error itself, without any backtrace attached to it.
+        pub fn root(&self) -> &IntCodeError {
+            match self {
+                IntCodeError::Traced { error, .. } => error.root(),
+                e => e,
+            }
+        }
+
+        pub fn backtrace(&self) -> Option<&backtrace::Backtrace> {
+            match self {
+                IntCodeError::Traced { backtrace, .. } => Some(backtrace),
+                _ => None,
+            }
+        }
+
+        // Waiting for input and halting are how a run stops, not faults.
+        fn is_fault(&self) -> bool {
+            !matches!(
+                self,
+                IntCodeError::NeedInput
+                    | IntCodeError::ProgramComplete
+                    | IntCodeError::Traced { .. }
+            )
+        }
+    }
+
+    impl fmt::Display for IntCodeError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                IntCodeError::NeedInput => write!(f, "program is waiting for input"),
+                IntCodeError::ProgramComplete => write!(f, "program has halted"),
+                IntCodeError::InvalidOpcode { address, value } => {
+                    write!(f, "invalid opcode {} at address {}", value, address)
+                }
+                IntCodeError::InvalidParameterMode {
+                    address,
+                    value,
+                    parameter,
+                } => write!(
+                    f,
+                    "invalid mode for parameter {} of instruction {} at address {}",
+                    parameter, value, address
+                ),
+                IntCodeError::ImmediateWrite { address, parameter } => write!(
+                    f,
+                    "parameter {} of the instruction at address {} is a write target in immediate mode",
+                    parameter, address
+                ),
+                IntCodeError::NegativeAddress { address, target } => write!(
+                    f,
+                    "instruction at address {} accessed negative address {}",
+                    address, target
+                ),
+                IntCodeError::JumpOutOfRange { address, target } => write!(
+                    f,
+                    "instruction at address {} jumped outside of memory to {}",
+                    address, target
+                ),
+                IntCodeError::MemoryLimitExceeded {
+                    address,
+                    target,
+                    limit,
+                } => write!(
+                    f,
+                    "instruction at address {} wrote to address {} beyond the memory limit of {} cells",
+                    address, target, limit
+                ),
+                IntCodeError::Device { address, message } => write!(
+                    f,
+                    "I/O device failed at address {}: {}",
+                    address, message
+                ),
+                IntCodeError::BudgetExhausted { address, executed } => write!(
+                    f,
+                    "instruction budget exhausted after {} instructions at address {}",
+                    executed, address
+                ),
+                IntCodeError::Overflow { address } => write!(
+                    f,
+                    "arithmetic overflow in the instruction at address {}",
+                    address
+                ),
+                IntCodeError::InfiniteLoop { address } => write!(
+                    f,
+                    "program is stuck in an infinite loop at address {}",
+                    address
+                ),
+                IntCodeError::ProtectionViolation {
+                    address,
+                    target,
+                    access,
+                } => write!(
+                    f,
+                    "{}",
+                    protect::Violation {
+                        address: *address,
+                        target: *target,
+                        access: *access,
+                    }
+                ),
+                IntCodeError::Traced { error, backtrace } => write!(f, "{}\n{}", error, backtrace),
+            }
+        }
     }
 
-    pub struct IntCodeMachine {
+    impl std::error::Error for IntCodeError {}
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+    pub enum RunState<W = i64> {
+        Output(W),
+        AwaitingInput,
+        Halted,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct IntCodeMachine<W: Word = i64> {
         instruction: usize,
         relative_base: i64,
-        registers: Vec<i64>,
-        input: VecDeque<i64>,
+        registers: memory::Memory<W>,
+        memory_limit: Option<usize>,
+        input: VecDeque<W>,
+        devices: io::Devices,
+        trace: trace::TraceHook,
+        profile: Option<profile::Profile>,
+        decoded: Vec<Option<Decoded>>,
+        use_decode_cache: bool,
+        // The length of the program the machine was loaded with.
+        program_length: usize,
+        instruction_count: u64,
+        instruction_budget: Option<u64>,
+        loop_detector: Option<watchdog::LoopDetector>,
+        protection: Option<protect::ProtectionMap>,
+        backtraces: bool,
+        // The functions found in the program image, kept while backtraces are enabled and
+        // dropped, like `decoded`, when the image is written to.
+        decompiled: Option<Arc<decompile::Decompiled>>,
+    }
+
+    // An instruction word that has already been split into its opcode and parameter modes.
+    #[derive(Clone, Copy, Debug)]
+    struct Decoded {
+        opcode: OpCode,
+        modes: [ParameterMode; 3],
+    }
+
+    // Memory grows with zeros on demand, so two machines whose registers differ only in
+    // zeros (or in backend) are in the same state.
+    impl<W: Word> PartialEq for IntCodeMachine<W> {
+        fn eq(&self, other: &Self) -> bool {
+            self.instruction == other.instruction
+                && self.relative_base == other.relative_base
+                && self.input == other.input
+                && self.registers.cells() == other.registers.cells()
+        }
+    }
+
+    impl<W: Word> Eq for IntCodeMachine<W> {}
+
+    impl<W: Word> Hash for IntCodeMachine<W> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.instruction.hash(state);
+            self.relative_base.hash(state);
+            self.input.hash(state);
+            self.registers.cells().hash(state);
+        }
     }
 
     impl IntCodeMachine {
-        pub fn new(program: &Vec<i64>) -> IntCodeMachine {
+        pub fn new(program: &[i64]) -> IntCodeMachine {
+            IntCodeMachine::with_memory(program, memory::MemoryBackend::Dense)
+        }
+
+        pub fn with_memory(program: &[i64], backend: memory::MemoryBackend) -> IntCodeMachine {
+            IntCodeMachine::from_program(program, backend)
+        }
+
+        pub fn set_input_source<S: io::InputSource + Send + 'static>(&mut self, source: S) {
+            self.devices.input = Some(Box::new(source));
+        }
+
+        pub fn clear_input_source(&mut self) -> Option<Box<dyn io::InputSource + Send>> {
+            self.devices.input.take()
+        }
+
+        pub fn set_output_sink<S: io::OutputSink + Send + 'static>(&mut self, sink: S) {
+            self.devices.output = Some(Box::new(sink));
+        }
+
+        pub fn clear_output_sink(&mut self) -> Option<Box<dyn io::OutputSink + Send>> {
+            self.devices.output.take()
+        }
+
+        pub fn set_tracer<S: trace::TraceSink + Send + 'static>(&mut self, sink: S) {
+            self.trace.attach(Box::new(sink));
+        }
+
+        pub fn clear_tracer(&mut self) -> Option<Box<dyn trace::TraceSink + Send>> {
+            self.trace.detach()
+        }
+    }
+
+    impl<W: Word> IntCodeMachine<W> {
+        // Loads an ordinary Intcode program into a machine with a wider word type, e.g.
+        // `IntCodeMachine::<BigInt>::from_program(&program, MemoryBackend::Dense)`.
+        pub fn from_program(program: &[i64], backend: memory::MemoryBackend) -> IntCodeMachine<W> {
+            let words: Vec<W> = program.iter().map(|v| W::from_i64(*v)).collect();
             IntCodeMachine {
                 instruction: 0,
                 relative_base: 0,
-                registers: program.clone(),
+                registers: memory::Memory::new(backend, &words),
+                memory_limit: Some(memory::DEFAULT_MEMORY_LIMIT),
                 input: VecDeque::new(),
+                devices: io::Devices::default(),
+                trace: trace::TraceHook::default(),
+                profile: None,
+                decoded: vec![None; program.len()],
+                use_decode_cache: true,
+                program_length: program.len(),
+                instruction_count: 0,
+                instruction_budget: None,
+                loop_detector: None,
+                protection: None,
+                backtraces: false,
+                decompiled: None,
             }
         }
 
-        pub fn provide_input(&mut self, input: i64) {
+        pub fn memory_backend(&self) -> memory::MemoryBackend {
+            self.registers.backend()
+        }
+
+        // Caps the number of memory cells the machine may allocate, `DEFAULT_MEMORY_LIMIT`
+        // unless changed. A write that would go over the limit fails with
+        // `MemoryLimitExceeded` instead.
+        pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+            self.memory_limit = limit;
+        }
+
+        pub fn provide_input(&mut self, input: W) {
             self.input.push_back(input);
         }
 
-        pub fn get_output(&mut self) -> Option<i64> {
-            match self.run_program() {
-                Ok(v) => Some(v),
-                Err(IntCodeError::ProgramComplete) => None,
+        pub fn get_output(&mut self) -> Option<W> {
+            match self.run() {
+                Ok(RunState::Output(v)) => Some(v),
                 _ => None,
             }
         }
 
-        pub fn get_output_v2(&mut self) -> Result<i64, IntCodeError> {
+        #[deprecated(note = "use `run`, which reports halting and input waits as a `RunState`")]
+        pub fn get_output_v2(&mut self) -> Result<W, IntCodeError> {
             self.run_program()
         }
 
-        pub fn run_program(&mut self) -> Result<i64, IntCodeError> {
+        pub fn run(&mut self) -> Result<RunState<W>, IntCodeError> {
             loop {
-                let instruction: i64 = self.registers[self.instruction];
-                let opcode = OpCode::from_instruction(instruction);
-                let parameter_mode_a = ParameterMode::from_instruction_and_number(instruction, 1);
-                let parameter_mode_b = ParameterMode::from_instruction_and_number(instruction, 2);
-                let parameter_mode_c = ParameterMode::from_instruction_and_number(instruction, 3);
-
-                let step: i64;
-                match opcode {
-                    OpCode::End => return Err(IntCodeError::ProgramComplete),
-                    OpCode::Add => {
-                        step = 4;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-                        let target = self.get_parameter_as_address(3, parameter_mode_c);
-
-                        self.registers[target as usize] = left_operand + right_operand;
-                    }
-                    OpCode::Multiply => {
-                        step = 4;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-                        let target = self.get_parameter_as_address(3, parameter_mode_c);
+                match self.step() {
+                    Ok(Some(v)) => return Ok(RunState::Output(v)),
+                    Ok(None) => (),
+                    Err(IntCodeError::NeedInput) => return Ok(RunState::AwaitingInput),
+                    Err(IntCodeError::ProgramComplete) => return Ok(RunState::Halted),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
 
-                        self.registers[target as usize] = left_operand * right_operand;
-                    }
-                    OpCode::Input => {
-                        step = 2;
-                        let target = self.get_parameter_as_address(1, parameter_mode_a);
+        pub fn is_halted(&self) -> bool {
+            self.current_opcode() == Some(OpCode::End)
+        }
+
+        // Whether the next instruction reads input and none is queued. An attached input
+        // source can only be asked by reading from it, so a machine with one is never
+        // reported as waiting; `run` returns `AwaitingInput` if the source has nothing.
+        pub fn is_awaiting_input(&self) -> bool {
+            self.input.is_empty()
+                && self.devices.input.is_none()
+                && self.current_opcode() == Some(OpCode::Input)
+        }
 
-                        let input = match self.input.pop_front() {
-                            Some(v) => v,
-                            None => return Err(IntCodeError::NeedInput),
-                        };
+        fn current_opcode(&self) -> Option<OpCode> {
+            self.read(self.instruction)
+                .to_i64()
+                .and_then(OpCode::try_from_instruction)
+        }
 
-                        self.registers[target as usize] = input;
-                    }
-                    OpCode::Output => {
-                        step = 2;
-                        let operand = self.get_parameter(1, parameter_mode_a);
+        pub fn run_program(&mut self) -> Result<W, IntCodeError> {
+            loop {
+                if let Some(v) = self.step()? {
+                    return Ok(v);
+                }
+            }
+        }
 
-                        self.instruction += step as usize;
-                        return Ok(operand);
+        pub fn enable_profiling(&mut self) {
+            if self.profile.is_none() {
+                self.profile = Some(profile::Profile::default());
+            }
+        }
+
+        pub fn profile(&self) -> Option<&profile::Profile> {
+            self.profile.as_ref()
+        }
+
+        pub fn take_profile(&mut self) -> Option<profile::Profile> {
+            self.profile.take()
+        }
+
+        pub fn instruction_count(&self) -> u64 {
+            self.instruction_count
+        }
+
+        // Stops the machine with `BudgetExhausted` once `instruction_count` reaches `budget`.
+        pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+            self.instruction_budget = budget;
+        }
+
+        // Stops the machine with `InfiniteLoop` when it repeats a state without doing I/O. The
+        // detector keeps a single saved state, so it costs constant memory however long the
+        // program runs between outputs; see `watchdog` for how soon a loop is caught.
+        pub fn set_loop_detection(&mut self, enabled: bool) {
+            self.loop_detector = match enabled {
+                true => Some(watchdog::LoopDetector::new(&self.registers)),
+                false => None,
+            };
+        }
+
+        // Checks the program's writes and jumps against `protection`, see `protect`.
+        pub fn set_protection(&mut self, protection: Option<protect::ProtectionMap>) {
+            self.protection = protection;
+        }
+
+        // Makes the loaded program image read-only.
+        pub fn protect_program(&mut self, mode: protect::ProtectionMode) {
+            self.protection = Some(protect::ProtectionMap::program_image(
+                self.program_length,
+                mode,
+            ));
+        }
+
+        pub fn protection(&self) -> Option<&protect::ProtectionMap> {
+            self.protection.as_ref()
+        }
+
+        pub fn protection_mut(&mut self) -> Option<&mut protect::ProtectionMap> {
+            self.protection.as_mut()
+        }
+
+        pub fn take_protection(&mut self) -> Option<protect::ProtectionMap> {
+            self.protection.take()
+        }
+
+        // Attaches a backtrace to every fault as `IntCodeError::Traced`.
+        pub fn set_backtraces(&mut self, enabled: bool) {
+            self.backtraces = enabled;
+            self.decompiled = match enabled {
+                true => Some(Arc::new(self.decompile_program())),
+                false => None,
+            };
+        }
+
+        // The call stack at the current instruction, unwound through the functions found in
+        // the loaded program.
+        pub fn backtrace(&self) -> backtrace::Backtrace {
+            let fresh;
+            let decompiled = match self.decompiled.as_ref() {
+                Some(decompiled) => decompiled.as_ref(),
+                None => {
+                    fresh = self.decompile_program();
+                    &fresh
+                }
+            };
+            backtrace::capture(
+                decompiled,
+                |address| self.read(address).saturating_i64(),
+                self.instruction,
+                self.relative_base,
+            )
+        }
+
+        fn decompile_program(&self) -> decompile::Decompiled {
+            let code: Vec<i64> = self
+                .peek_range(0, self.program_length)
+                .iter()
+                .map(|w| w.saturating_i64())
+                .collect();
+            decompile::decompile(&code)
+        }
+
+        pub fn peek(&self, address: usize) -> W {
+            self.read(address)
+        }
+
+        pub fn peek_range(&self, start: usize, length: usize) -> Vec<W> {
+            self.registers.read_range(start, length)
+        }
+
+        // Pokes go through the same path as instruction writes, so they respect the memory
+        // limit and are seen by the decode cache and loop detector, but they are not traced.
+        pub fn poke(&mut self, address: usize, value: W) -> Result<(), IntCodeError> {
+            self.write(address, value)
+        }
+
+        pub fn poke_range(&mut self, start: usize, values: &[W]) -> Result<(), IntCodeError> {
+            for (offset, value) in values.iter().enumerate() {
+                let address =
+                    start
+                        .checked_add(offset)
+                        .ok_or_else(|| IntCodeError::MemoryLimitExceeded {
+                            address: self.instruction,
+                            target: usize::MAX,
+                            limit: self.memory_limit.unwrap_or(usize::MAX),
+                        })?;
+                self.write(address, value.clone())?;
+            }
+            Ok(())
+        }
+
+        pub fn step(&mut self) -> Result<Option<W>, IntCodeError> {
+            match self.advance() {
+                Err(e) if self.backtraces && e.is_fault() => {
+                    if self.decompiled.is_none() {
+                        self.decompiled = Some(Arc::new(self.decompile_program()));
                     }
-                    OpCode::JumpIfTrue => {
-                        step = 3;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-
-                        if left_operand != 0 {
-                            self.instruction = right_operand as usize;
-                            continue;
-                        }
+                    Err(IntCodeError::Traced {
+                        error: Box::new(e),
+                        backtrace: self.backtrace(),
+                    })
+                }
+                result => result,
+            }
+        }
+
+        fn advance(&mut self) -> Result<Option<W>, IntCodeError> {
+            let address = self.instruction;
+            if let Some(budget) = self.instruction_budget {
+                if self.instruction_count >= budget {
+                    return Err(IntCodeError::BudgetExhausted {
+                        address,
+                        executed: self.instruction_count,
+                    });
+                }
+            }
+
+            let state = match self.loop_detector.as_ref() {
+                Some(detector) => {
+                    let state = detector.state(address, self.relative_base);
+                    if detector.seen(&state) {
+                        return Err(IntCodeError::InfiniteLoop { address });
                     }
-                    OpCode::JumpIfFalse => {
-                        step = 3;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-
-                        if left_operand == 0 {
-                            self.instruction = right_operand as usize;
-                            continue;
-                        }
+                    Some((state, self.current_opcode()))
+                }
+                None => None,
+            };
+
+            let instruction = self
+                .profile
+                .as_ref()
+                .map(|_| self.read(address).saturating_i64());
+            let result = self.execute();
+            if result.is_ok() {
+                self.instruction_count += 1;
+            }
+            if let (Some(detector), Some((state, opcode)), Ok(output)) =
+                (self.loop_detector.as_mut(), state, &result)
+            {
+                if output.is_some() || opcode == Some(OpCode::Input) {
+                    detector.io();
+                } else {
+                    detector.record(state);
+                }
+            }
+            self.trace.finish(&result);
+            if let (Some(profile), Some(instruction)) = (self.profile.as_mut(), instruction) {
+                profile.record(address, instruction, &result, self.instruction);
+            }
+
+            // Sinks can only be attached to `i64` machines, so the conversion is exact.
+            match (result, self.devices.output.as_mut()) {
+                (Ok(Some(v)), Some(sink)) => match sink.write(v.saturating_i64()) {
+                    Ok(()) => Ok(None),
+                    Err(e) => Err(IntCodeError::Device {
+                        address,
+                        message: e.to_string(),
+                    }),
+                },
+                (result, _) => result,
+            }
+        }
+
+        // Decoded instructions within the loaded program are cached per address and dropped
+        // whenever that address is written, so self-modifying programs still see their new code.
+        pub fn set_decode_cache(&mut self, enabled: bool) {
+            self.use_decode_cache = enabled;
+            for decoded in self.decoded.iter_mut() {
+                *decoded = None;
+            }
+        }
+
+        fn decode(&mut self) -> Result<Decoded, IntCodeError> {
+            if let Some(Some(decoded)) = self.decoded.get(self.instruction) {
+                return Ok(*decoded);
+            }
+
+            let word = self.read(self.instruction);
+            let opcode = match word.to_i64().and_then(OpCode::try_from_instruction) {
+                Some(opcode) => opcode,
+                None => {
+                    return Err(IntCodeError::InvalidOpcode {
+                        address: self.instruction,
+                        value: word.saturating_i64(),
+                    })
+                }
+            };
+            let instruction = word.saturating_i64();
+            let decoded = Decoded {
+                opcode,
+                modes: [
+                    self.get_parameter_mode(instruction, 1)?,
+                    self.get_parameter_mode(instruction, 2)?,
+                    self.get_parameter_mode(instruction, 3)?,
+                ],
+            };
+
+            if self.use_decode_cache {
+                if let Some(slot) = self.decoded.get_mut(self.instruction) {
+                    *slot = Some(decoded);
+                }
+            }
+
+            Ok(decoded)
+        }
+
+        fn execute(&mut self) -> Result<Option<W>, IntCodeError> {
+            let Decoded { opcode, modes } = self.decode()?;
+            self.trace.begin(self.instruction, opcode);
+            let [parameter_mode_a, parameter_mode_b, parameter_mode_c] = modes;
+
+            let step: i64;
+            match opcode {
+                OpCode::End => return Err(IntCodeError::ProgramComplete),
+                OpCode::Add => {
+                    step = 4;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+                    let target = self.get_parameter_as_address(3, parameter_mode_c)?;
+
+                    let sum = self.checked(left_operand.checked_add(&right_operand))?;
+                    self.store(target, sum)?;
+                }
+                OpCode::Multiply => {
+                    step = 4;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+                    let target = self.get_parameter_as_address(3, parameter_mode_c)?;
+
+                    let product = self.checked(left_operand.checked_mul(&right_operand))?;
+                    self.store(target, product)?;
+                }
+                OpCode::Input => {
+                    step = 2;
+                    let target = self.get_parameter_as_address(1, parameter_mode_a)?;
+
+                    let input = match self.next_input()? {
+                        Some(v) => v,
+                        None => return Err(IntCodeError::NeedInput),
+                    };
+
+                    self.store(target, input)?;
+                }
+                OpCode::Output => {
+                    step = 2;
+                    let operand = self.get_parameter(1, parameter_mode_a)?;
+
+                    self.transfer(self.instruction + step as usize)?;
+                    return Ok(Some(operand));
+                }
+                OpCode::JumpIfTrue => {
+                    step = 3;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+
+                    if !left_operand.is_zero() {
+                        let target = self.word_to_i64(&right_operand)?;
+                        self.jump(target)?;
+                        return Ok(None);
                     }
-                    OpCode::LessThan => {
-                        step = 4;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-                        let target = self.get_parameter_as_address(3, parameter_mode_c);
-
-                        if left_operand < right_operand {
-                            self.registers[target] = 1;
-                        } else {
-                            self.registers[target] = 0;
-                        }
+                }
+                OpCode::JumpIfFalse => {
+                    step = 3;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+
+                    if left_operand.is_zero() {
+                        let target = self.word_to_i64(&right_operand)?;
+                        self.jump(target)?;
+                        return Ok(None);
                     }
-                    OpCode::Equals => {
-                        step = 4;
-                        let left_operand = self.get_parameter(1, parameter_mode_a);
-                        let right_operand = self.get_parameter(2, parameter_mode_b);
-                        let target = self.get_parameter_as_address(3, parameter_mode_c);
-
-                        if left_operand == right_operand {
-                            self.registers[target] = 1;
-                        } else {
-                            self.registers[target] = 0;
-                        }
+                }
+                OpCode::LessThan => {
+                    step = 4;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+                    let target = self.get_parameter_as_address(3, parameter_mode_c)?;
+
+                    if left_operand < right_operand {
+                        self.store(target, W::from_i64(1))?;
+                    } else {
+                        self.store(target, W::zero())?;
                     }
-                    OpCode::RelativeBaseOffset => {
-                        step = 2;
-                        let operand = self.get_parameter(1, parameter_mode_a);
-                        self.relative_base += operand;
+                }
+                OpCode::Equals => {
+                    step = 4;
+                    let left_operand = self.get_parameter(1, parameter_mode_a)?;
+                    let right_operand = self.get_parameter(2, parameter_mode_b)?;
+                    let target = self.get_parameter_as_address(3, parameter_mode_c)?;
+
+                    if left_operand == right_operand {
+                        self.store(target, W::from_i64(1))?;
+                    } else {
+                        self.store(target, W::zero())?;
                     }
                 }
+                OpCode::RelativeBaseOffset => {
+                    step = 2;
+                    let operand = self.get_parameter(1, parameter_mode_a)?;
+                    let offset = self.word_to_i64(&operand)?;
+                    self.relative_base = self.relative_offset(offset)?;
+                    self.trace.relative_base(self.relative_base);
+                }
+            }
+
+            self.transfer(self.instruction + step as usize)?;
+            Ok(None)
+        }
 
-                self.instruction += step as usize;
+        fn protect(&mut self, target: usize, access: protect::Access) -> Result<(), IntCodeError> {
+            let address = self.instruction;
+            match self.protection.as_mut() {
+                Some(protection) => protection.check(address, target, access).map_err(|v| {
+                    IntCodeError::ProtectionViolation {
+                        address: v.address,
+                        target: v.target,
+                        access: v.access,
+                    }
+                }),
+                None => Ok(()),
             }
         }
 
-        fn get_parameter(&mut self, number: i64, mode: ParameterMode) -> i64 {
-            match mode {
-                ParameterMode::Position => {
-                    let index_1 = self.instruction + number as usize;
-                    self.ensure_registers_have_index(index_1);
-                    let index_2 = self.registers[index_1] as usize;
-                    self.ensure_registers_have_index(index_2);
+        // A write made by the program itself, as opposed to a `poke`.
+        fn store(&mut self, target: usize, value: W) -> Result<(), IntCodeError> {
+            self.protect(target, protect::Access::Write)?;
+            self.write(target, value)
+        }
 
-                    self.registers[index_2]
-                }
-                ParameterMode::Immediate => {
-                    let index = self.instruction + number as usize;
-                    self.ensure_registers_have_index(index);
-                    self.registers[index]
-                }
-                ParameterMode::Relative => {
-                    let index = (self.registers[self.instruction + number as usize]
-                        + self.relative_base as i64) as usize;
-                    self.ensure_registers_have_index(index);
-                    self.registers[index]
-                }
+        fn checked(&self, value: Option<W>) -> Result<W, IntCodeError> {
+            value.ok_or(IntCodeError::Overflow {
+                address: self.instruction,
+            })
+        }
+
+        fn next_input(&mut self) -> Result<Option<W>, IntCodeError> {
+            if let Some(v) = self.input.pop_front() {
+                return Ok(Some(v));
+            }
+
+            match self.devices.input.as_mut() {
+                Some(source) => match source.read() {
+                    Ok(v) => Ok(v.map(W::from_i64)),
+                    Err(e) => Err(IntCodeError::Device {
+                        address: self.instruction,
+                        message: e.to_string(),
+                    }),
+                },
+                None => Ok(None),
+            }
+        }
+
+        fn read(&self, index: usize) -> W {
+            self.registers.get(index)
+        }
+
+        fn get_parameter_mode(
+            &self,
+            instruction: i64,
+            number: i64,
+        ) -> Result<ParameterMode, IntCodeError> {
+            match ParameterMode::try_from_instruction_and_number(instruction, number) {
+                Some(mode) => Ok(mode),
+                None => Err(IntCodeError::InvalidParameterMode {
+                    address: self.instruction,
+                    value: instruction,
+                    parameter: number,
+                }),
             }
         }
 
-        fn get_parameter_as_address(&mut self, number: i64, mode: ParameterMode) -> usize {
+        fn get_parameter(&mut self, number: i64, mode: ParameterMode) -> Result<W, IntCodeError> {
+            let value = self.read(self.instruction + number as usize);
             let result = match mode {
                 ParameterMode::Position => {
-                    let index = self.instruction + number as usize;
-                    self.ensure_registers_have_index(index);
-                    self.registers[index]
+                    let index = self.word_to_i64(&value)?;
+                    self.read(self.to_address(index)?)
                 }
+                ParameterMode::Immediate => value,
                 ParameterMode::Relative => {
-                    let index = self.instruction + number as usize;
-                    self.ensure_registers_have_index(index);
-                    self.registers[index] + self.relative_base as i64
+                    let offset = self.word_to_i64(&value)?;
+                    let index = self.relative_offset(offset)?;
+                    self.read(self.to_address(index)?)
                 }
+            };
+
+            self.trace.operand(&result);
+            Ok(result)
+        }
+
+        fn get_parameter_as_address(
+            &mut self,
+            number: i64,
+            mode: ParameterMode,
+        ) -> Result<usize, IntCodeError> {
+            let value = self.word_to_i64(&self.read(self.instruction + number as usize))?;
+            let result = match mode {
+                ParameterMode::Position => self.to_address(value)?,
+                ParameterMode::Relative => self.to_address(self.relative_offset(value)?)?,
                 ParameterMode::Immediate => {
-                    panic!("Attempted to acquire parameter as address in immediate mode")
+                    return Err(IntCodeError::ImmediateWrite {
+                        address: self.instruction,
+                        parameter: number,
+                    })
                 }
             };
 
-            let result = result as usize;
-            self.ensure_registers_have_index(result);
-            result
+            self.trace.operand(&(result as i64));
+            Ok(result)
+        }
+
+        fn write(&mut self, index: usize, value: W) -> Result<(), IntCodeError> {
+            let within = match (
+                self.registers.allocated_after_write(index),
+                self.memory_limit,
+            ) {
+                (Some(allocated), Some(limit)) => allocated <= limit,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if !within {
+                return Err(IntCodeError::MemoryLimitExceeded {
+                    address: self.instruction,
+                    target: index,
+                    limit: self.memory_limit.unwrap_or(usize::MAX),
+                });
+            }
+
+            if let Some(detector) = self.loop_detector.as_mut() {
+                detector.write(index, &self.registers.get(index), &value);
+            }
+            self.trace.write(index, &value);
+            self.registers.set(index, value);
+            if let Some(decoded) = self.decoded.get_mut(index) {
+                *decoded = None;
+                self.decompiled = None;
+            }
+            Ok(())
+        }
+
+        // Addresses, jump targets and relative offsets are 64-bit, so a wider word that does
+        // not fit is an overflow.
+        fn word_to_i64(&self, value: &W) -> Result<i64, IntCodeError> {
+            value.to_i64().ok_or(IntCodeError::Overflow {
+                address: self.instruction,
+            })
         }
 
-        fn ensure_registers_have_index(&mut self, index: usize) -> () {
-            if index >= self.registers.len() {
-                self.registers.resize(index + 1, 0);
+        fn relative_offset(&self, offset: i64) -> Result<i64, IntCodeError> {
+            self.relative_base
+                .checked_add(offset)
+                .ok_or(IntCodeError::Overflow {
+                    address: self.instruction,
+                })
+        }
+
+        fn to_address(&self, value: i64) -> Result<usize, IntCodeError> {
+            if value < 0 {
+                return Err(IntCodeError::NegativeAddress {
+                    address: self.instruction,
+                    target: value,
+                });
             }
+
+            Ok(value as usize)
+        }
+
+        fn jump(&mut self, target: i64) -> Result<(), IntCodeError> {
+            if target < 0 || target as usize >= self.registers.len() {
+                return Err(IntCodeError::JumpOutOfRange {
+                    address: self.instruction,
+                    target,
+                });
+            }
+
+            self.transfer(target as usize)
+        }
+
+        // Moves to the next instruction, which must be executable.
+        fn transfer(&mut self, target: usize) -> Result<(), IntCodeError> {
+            self.protect(target, protect::Access::Execute)?;
+            self.instruction = target;
+            Ok(())
         }
     }
 
-    #[derive(PartialEq, Debug)]
+    #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
     pub enum OpCode {
         Add,
         Multiply,
@@ -236,34 +941,112 @@ pub mod intcode {
 
     impl From<i64> for OpCode {
         fn from(i: i64) -> Self {
-            match i {
-                1 => OpCode::Add,
-                2 => OpCode::Multiply,
-                3 => OpCode::Input,
-                4 => OpCode::Output,
-                5 => OpCode::JumpIfTrue,
-                6 => OpCode::JumpIfFalse,
-                7 => OpCode::LessThan,
-                8 => OpCode::Equals,
-                9 => OpCode::RelativeBaseOffset,
-                99 => OpCode::End,
-                _ => panic!("Bad opcode: {}", i),
+            match OpCode::from_code(i) {
+                Some(opcode) => opcode,
+                None => panic!("Bad opcode: {}", i),
             }
         }
     }
 
     impl OpCode {
+        pub fn from_code(i: i64) -> Option<Self> {
+            match i {
+                1 => Some(OpCode::Add),
+                2 => Some(OpCode::Multiply),
+                3 => Some(OpCode::Input),
+                4 => Some(OpCode::Output),
+                5 => Some(OpCode::JumpIfTrue),
+                6 => Some(OpCode::JumpIfFalse),
+                7 => Some(OpCode::LessThan),
+                8 => Some(OpCode::Equals),
+                9 => Some(OpCode::RelativeBaseOffset),
+                99 => Some(OpCode::End),
+                _ => None,
+            }
+        }
+
         pub fn from_instruction(i: i64) -> Self {
+            match OpCode::try_from_instruction(i) {
+                Some(opcode) => opcode,
+                None => panic!("Bad opcode: {}", i),
+            }
+        }
+
+        pub fn try_from_instruction(i: i64) -> Option<Self> {
             let last_two_digits: i64 = i % 100;
             if last_two_digits > 9 {
-                OpCode::from(((last_two_digits % 10) * 10) + last_two_digits / 10)
+                OpCode::from_code(((last_two_digits % 10) * 10) + last_two_digits / 10)
             } else {
-                OpCode::from(last_two_digits)
+                OpCode::from_code(last_two_digits)
+            }
+        }
+
+        pub fn code(&self) -> i64 {
+            match self {
+                OpCode::Add => 1,
+                OpCode::Multiply => 2,
+                OpCode::Input => 3,
+                OpCode::Output => 4,
+                OpCode::JumpIfTrue => 5,
+                OpCode::JumpIfFalse => 6,
+                OpCode::LessThan => 7,
+                OpCode::Equals => 8,
+                OpCode::RelativeBaseOffset => 9,
+                OpCode::End => 99,
+            }
+        }
+
+        pub fn mnemonic(&self) -> &'static str {
+            match self {
+                OpCode::Add => "add",
+                OpCode::Multiply => "mul",
+                OpCode::Input => "in",
+                OpCode::Output => "out",
+                OpCode::JumpIfTrue => "jt",
+                OpCode::JumpIfFalse => "jf",
+                OpCode::LessThan => "lt",
+                OpCode::Equals => "eq",
+                OpCode::RelativeBaseOffset => "rbo",
+                OpCode::End => "end",
+            }
+        }
+
+        pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+            match mnemonic {
+                "add" => Some(OpCode::Add),
+                "mul" => Some(OpCode::Multiply),
+                "in" => Some(OpCode::Input),
+                "out" => Some(OpCode::Output),
+                "jt" => Some(OpCode::JumpIfTrue),
+                "jf" => Some(OpCode::JumpIfFalse),
+                "lt" => Some(OpCode::LessThan),
+                "eq" => Some(OpCode::Equals),
+                "rbo" => Some(OpCode::RelativeBaseOffset),
+                "end" => Some(OpCode::End),
+                _ => None,
+            }
+        }
+
+        pub fn parameter_count(&self) -> usize {
+            match self {
+                OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => 3,
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse => 2,
+                OpCode::Input | OpCode::Output | OpCode::RelativeBaseOffset => 1,
+                OpCode::End => 0,
+            }
+        }
+
+        // The parameter number (1-based) that names a memory cell to write, if any.
+        pub fn write_parameter(&self) -> Option<i64> {
+            match self {
+                OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => Some(3),
+                OpCode::Input => Some(1),
+                _ => None,
             }
         }
     }
 
-    #[derive(PartialEq, Debug)]
+    #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
     pub enum ParameterMode {
         Position,
         Immediate,
@@ -272,32 +1055,53 @@ pub mod intcode {
 
     impl From<i64> for ParameterMode {
         fn from(i: i64) -> Self {
-            match i {
-                0 => ParameterMode::Position,
-                1 => ParameterMode::Immediate,
-                2 => ParameterMode::Relative,
-                _ => panic!("Bad parameter mode: {}", i),
+            match ParameterMode::from_code(i) {
+                Some(mode) => mode,
+                None => panic!("Bad parameter mode: {}", i),
             }
         }
     }
 
     impl ParameterMode {
+        pub fn from_code(i: i64) -> Option<Self> {
+            match i {
+                0 => Some(ParameterMode::Position),
+                1 => Some(ParameterMode::Immediate),
+                2 => Some(ParameterMode::Relative),
+                _ => None,
+            }
+        }
+
         pub fn from_instruction_and_number(opcode: i64, number: i64) -> ParameterMode {
-            let digit = match number {
+            ParameterMode::from(ParameterMode::digit(opcode, number))
+        }
+
+        pub fn try_from_instruction_and_number(opcode: i64, number: i64) -> Option<ParameterMode> {
+            ParameterMode::from_code(ParameterMode::digit(opcode, number))
+        }
+
+        fn digit(opcode: i64, number: i64) -> i64 {
+            match number {
                 1 => (opcode / 100) % 10,
                 2 => (opcode / 1000) % 10,
                 3 => (opcode / 10000) % 10,
                 _ => panic!("Invalid parameter number: {}", number),
-            };
+            }
+        }
 
-            ParameterMode::from(digit)
+        pub fn code(&self) -> i64 {
+            match self {
+                ParameterMode::Position => 0,
+                ParameterMode::Immediate => 1,
+                ParameterMode::Relative => 2,
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::intcode::{IntCodeMachine, OpCode, ParameterMode};
+    use super::intcode::{IntCodeError, IntCodeMachine, OpCode, ParameterMode, RunState};
 
     #[test]
     fn test_opcode_construction() {
@@ -404,6 +1208,115 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_run_states() {
+        let input = vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0];
+
+        let mut machine = IntCodeMachine::new(&input);
+        assert!(!machine.is_halted());
+        assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+        assert!(machine.is_awaiting_input());
+        assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+
+        machine.provide_input(5);
+        assert!(!machine.is_awaiting_input());
+        assert_eq!(machine.run(), Ok(RunState::Output(5)));
+        assert_eq!(machine.run(), Ok(RunState::AwaitingInput));
+
+        machine.provide_input(6);
+        assert_eq!(machine.run(), Ok(RunState::Output(6)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert!(machine.is_halted());
+        assert!(!machine.is_awaiting_input());
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+
+        let mut machine = IntCodeMachine::new(&[3, 0, 4, 0, 99]);
+        machine.set_input_source(|| Some(8));
+        assert!(!machine.is_awaiting_input());
+        assert_eq!(machine.run(), Ok(RunState::Output(8)));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Prints 1, overwrites its first instruction with `end` and jumps back to it.
+        let input = vec![104, 1, 1101, 0, 99, 0, 1105, 1, 0];
+
+        for &cached in &[true, false] {
+            let mut machine = IntCodeMachine::new(&input);
+            machine.set_decode_cache(cached);
+            assert_eq!(machine.run(), Ok(RunState::Output(1)));
+            assert_eq!(machine.run(), Ok(RunState::Halted));
+        }
+    }
+
+    #[test]
+    fn test_faults_are_errors() {
+        let cases = vec![
+            (
+                vec![1, 0, 0, 0, 42],
+                IntCodeError::InvalidOpcode {
+                    address: 4,
+                    value: 42,
+                },
+            ),
+            (
+                vec![1, 0, 0, 0],
+                IntCodeError::InvalidOpcode {
+                    address: 4,
+                    value: 0,
+                },
+            ),
+            (
+                vec![304, 0, 99],
+                IntCodeError::InvalidParameterMode {
+                    address: 0,
+                    value: 304,
+                    parameter: 1,
+                },
+            ),
+            (
+                vec![11101, 1, 2, 3, 99],
+                IntCodeError::ImmediateWrite {
+                    address: 0,
+                    parameter: 3,
+                },
+            ),
+            (
+                vec![1, -7, 0, 0, 99],
+                IntCodeError::NegativeAddress {
+                    address: 0,
+                    target: -7,
+                },
+            ),
+            (
+                vec![109, -3, 22101, 1, 0, 0, 99],
+                IntCodeError::NegativeAddress {
+                    address: 2,
+                    target: -3,
+                },
+            ),
+            (
+                vec![1105, 1, 5000000000000, 99],
+                IntCodeError::JumpOutOfRange {
+                    address: 0,
+                    target: 5000000000000,
+                },
+            ),
+            (
+                vec![1106, 0, -1, 99],
+                IntCodeError::JumpOutOfRange {
+                    address: 0,
+                    target: -1,
+                },
+            ),
+        ];
+
+        for (program, error) in cases {
+            let mut machine = IntCodeMachine::new(&program);
+            assert_eq!(machine.run_program(), Err(error));
+        }
+    }
+
     #[test]
     fn test_quine() {
         let input = vec![